@@ -1,8 +1,93 @@
 
+pub mod errors{
+    use crate::map_tiles::TileOwner;
+    use std::fmt;
+
+    /// Errors from building, editing or loading battle maps
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum MapGenError{
+        /// The board is below the 4w x 2h minimum
+        BoardTooSmall { width: usize, height: usize },
+        /// A location is not on the board
+        OutOfBounds { row: usize, column: usize, width: usize, height: usize },
+        /// The board is above the `MAX_BOARD_TILES` limit
+        BoardTooLarge { width: usize, height: usize },
+        /// The campaign densities ask for more features than the board has tiles
+        DensityTooHigh { requested: u64, available: u64 },
+        /// The number of tiles does not match the board's dimensions
+        TileCountMismatch { expected: usize, found: usize },
+        /// Serialized map data was written by an unsupported format version
+        UnsupportedVersion { found: u32, expected: u32 },
+        /// Serialized map data could not be read
+        InvalidData(String),
+        /// Text could not be parsed as a tile, add-on or other map value
+        Parse(String),
+        /// A map file could not be read or written
+        Io(String),
+        /// Attacker and defender campaign hexes must be next to each other
+        NotAdjacent { attacker: (usize, usize), defender: (usize, usize) },
+        /// A deployment layout leaves an owner without any tiles
+        EmptyDeployment { owner: TileOwner },
+        /// A custom deployment mask is not the size of the board
+        MaskSizeMismatch { board: (usize, usize), mask: (usize, usize) },
+        /// A rule set already holds as many tiles as it can
+        TooManyTiles { max: usize },
+        /// A tile was used before being added to its rule set
+        UnknownTile,
+        /// A campaign map file could not be parsed, lines count from 1
+        CampaignFile { line: usize, message: String },
+    }
+
+    impl fmt::Display for MapGenError{
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            match self{
+                MapGenError::BoardTooSmall { width, height } =>
+                    write!(f, "Board must be at least 4w x 2h, and 3h when attacked from the side, was {}w x {}h", width, height),
+                MapGenError::OutOfBounds { row, column, width, height } =>
+                    write!(f, "Tile ({},{}) is not on the {}w x {}h board", row, column, width, height),
+                MapGenError::BoardTooLarge { width, height } =>
+                    write!(f, "Board can have at most {} tiles, was {}w x {}h", crate::battle_map::MAX_BOARD_TILES, width, height),
+                MapGenError::DensityTooHigh { requested, available } =>
+                    write!(f, "Campaign densities need {} tiles but the board only has {}", requested, available),
+                MapGenError::TileCountMismatch { expected, found } =>
+                    write!(f, "Board needs {} tiles, found {}", expected, found),
+                MapGenError::UnsupportedVersion { found, expected } =>
+                    write!(f, "Unsupported map data version {}, expected {}", found, expected),
+                MapGenError::InvalidData(e) => write!(f, "Invalid map data: {}", e),
+                MapGenError::Parse(e) => write!(f, "{}", e),
+                MapGenError::Io(e) => write!(f, "Map file error: {}", e),
+                MapGenError::NotAdjacent { attacker, defender } =>
+                    write!(f, "Attacker ({},{}) is not next to defender ({},{})", attacker.0, attacker.1, defender.0, defender.1),
+                MapGenError::CampaignFile { line, message } => write!(f, "Campaign file line {}: {}", line, message),
+                MapGenError::EmptyDeployment { owner } => write!(f, "Deployment layout gives the {:?} no tiles", owner),
+                MapGenError::MaskSizeMismatch { board, mask } =>
+                    write!(f, "Deployment mask is {}w x {}h but the board is {}w x {}h", mask.0, mask.1, board.0, board.1),
+                MapGenError::TooManyTiles { max } => write!(f, "Rule sets hold at most {} tiles", max),
+                MapGenError::UnknownTile => write!(f, "Tile must be added to the rules before use"),
+            }
+        }
+    }
+
+    impl std::error::Error for MapGenError{}
+
+    impl From<std::io::Error> for MapGenError{
+        fn from(e: std::io::Error) -> Self{
+            MapGenError::Io(e.to_string())
+        }
+    }
+}
+
+
 pub mod map_tiles{
+    use crate::battle_map::HexDirection;
+    use crate::errors::MapGenError;
     use colored::{Colorize, ColoredString};
+    use std::fmt;
+    use std::str::FromStr;
 
 
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum CampaignMapTileType{
         Forest,
         Hill,
@@ -16,6 +101,66 @@ pub mod map_tiles{
         Default
     }
 
+    impl fmt::Display for CampaignMapTileType{
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            let s = match self{
+                CampaignMapTileType::Forest => "Forest",
+                CampaignMapTileType::Hill => "Hill",
+                CampaignMapTileType::Mountain => "Mountain",
+                CampaignMapTileType::Outpost => "Outpost",
+                CampaignMapTileType::Plains => "Plains",
+                CampaignMapTileType::River => "River",
+                CampaignMapTileType::Road => "Road",
+                CampaignMapTileType::Swamp => "Swamp",
+                CampaignMapTileType::Town => "Town",
+                CampaignMapTileType::Default => "Default",
+            };
+            write!(f, "{}", s)
+        }
+    }
+
+    impl CampaignMapTileType{
+        /// The battle terrain a region should lean towards when generated from this campaign tile,
+        /// None for tiles whose features are placed by their own generation step (towns, rivers, etc.)
+        pub fn battle_terrain(&self) -> Option<BattleMapTileType>{
+            match self{
+                CampaignMapTileType::Forest => Some(BattleMapTileType::Forest),
+                CampaignMapTileType::Hill => Some(BattleMapTileType::Hill),
+                CampaignMapTileType::Mountain => Some(BattleMapTileType::Mountain),
+                CampaignMapTileType::Plains => Some(BattleMapTileType::Plains),
+                CampaignMapTileType::Swamp => Some(BattleMapTileType::Swamp),
+                CampaignMapTileType::Outpost
+                | CampaignMapTileType::River
+                | CampaignMapTileType::Road
+                | CampaignMapTileType::Town
+                | CampaignMapTileType::Default => None,
+            }
+        }
+    }
+
+    impl FromStr for CampaignMapTileType{
+        type Err = MapGenError;
+
+        /// Parse a tile type, ignoring case, e.g. "forest" or "Forest"
+        fn from_str(s: &str) -> Result<Self, Self::Err>{
+            match normalize_name(s).as_str(){
+                "forest" => Ok(CampaignMapTileType::Forest),
+                "hill" => Ok(CampaignMapTileType::Hill),
+                "mountain" => Ok(CampaignMapTileType::Mountain),
+                "outpost" => Ok(CampaignMapTileType::Outpost),
+                "plains" => Ok(CampaignMapTileType::Plains),
+                "river" => Ok(CampaignMapTileType::River),
+                "road" => Ok(CampaignMapTileType::Road),
+                "swamp" => Ok(CampaignMapTileType::Swamp),
+                "town" => Ok(CampaignMapTileType::Town),
+                "default" => Ok(CampaignMapTileType::Default),
+                _ => Err(MapGenError::Parse(format!("Unknown campaign tile type: {}", s))),
+            }
+        }
+    }
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum CampaignMapTileAddOn{
         Road,
         River,
@@ -23,13 +168,51 @@ pub mod map_tiles{
         RiverBridge
     }
 
+    impl fmt::Display for CampaignMapTileAddOn{
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            let s = match self{
+                CampaignMapTileAddOn::Road => "Road",
+                CampaignMapTileAddOn::River => "River",
+                CampaignMapTileAddOn::RiverFord => "RiverFord",
+                CampaignMapTileAddOn::RiverBridge => "RiverBridge",
+            };
+            write!(f, "{}", s)
+        }
+    }
+
+    impl FromStr for CampaignMapTileAddOn{
+        type Err = MapGenError;
+
+        /// Parse an add-on, ignoring case and any '_', '-' or ' ', e.g. "river_ford" or "RiverFord"
+        fn from_str(s: &str) -> Result<Self, Self::Err>{
+            match normalize_name(s).as_str(){
+                "road" => Ok(CampaignMapTileAddOn::Road),
+                "river" => Ok(CampaignMapTileAddOn::River),
+                "riverford" | "ford" => Ok(CampaignMapTileAddOn::RiverFord),
+                "riverbridge" | "bridge" => Ok(CampaignMapTileAddOn::RiverBridge),
+                _ => Err(MapGenError::Parse(format!("Unknown campaign tile add-on: {}", s))),
+            }
+        }
+    }
+
+    /// Lowercase a name and strip any separators so "River_Ford" and "riverford" parse the same
+    pub(crate) fn normalize_name(s: &str) -> String{
+        s.trim()
+            .chars()
+            .filter(|c| !matches!(c, '_' | '-' | ' '))
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    }
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct CampaignMapTile{
         tile: CampaignMapTileType,
         add_on: Option<CampaignMapTileAddOn>,
     }
 
-    impl CampaignMapTile{
-        pub fn default() -> Self{
+    impl Default for CampaignMapTile{
+        fn default() -> Self{
             CampaignMapTile { 
                 tile: CampaignMapTileType::Default, 
                 add_on: Option::None 
@@ -37,7 +220,55 @@ pub mod map_tiles{
         }
     }
 
+    impl CampaignMapTile{
+        pub fn new(tile: CampaignMapTileType, add_on: Option<CampaignMapTileAddOn>) -> Self{
+            CampaignMapTile { tile, add_on }
+        }
+
+        pub fn get_type(&self) -> &CampaignMapTileType{
+            &self.tile
+        }
+
+        pub fn get_add_on(&self) -> Option<&CampaignMapTileAddOn>{
+            self.add_on.as_ref()
+        }
+
+        /// Whether a road runs through this tile, either as the tile itself or as an add-on
+        pub fn has_road(&self) -> bool{
+            self.tile == CampaignMapTileType::Road || self.add_on == Some(CampaignMapTileAddOn::Road)
+        }
+
+        /// Whether a river runs through this tile, either as the tile itself or as an add-on
+        pub fn has_river(&self) -> bool{
+            self.tile == CampaignMapTileType::River || matches!(self.add_on,
+                Some(CampaignMapTileAddOn::River | CampaignMapTileAddOn::RiverFord | CampaignMapTileAddOn::RiverBridge))
+        }
+    }
+
+    impl fmt::Display for CampaignMapTile{
+        /// Formats as "Type" or "Type+AddOn", e.g. "Forest+RiverBridge"
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            match &self.add_on{
+                Some(a) => write!(f, "{}+{}", self.tile, a),
+                None => write!(f, "{}", self.tile),
+            }
+        }
+    }
+
+    impl FromStr for CampaignMapTile{
+        type Err = MapGenError;
+
+        /// Parse "Type" or "Type+AddOn", the inverse of Display
+        fn from_str(s: &str) -> Result<Self, Self::Err>{
+            match s.split_once('+'){
+                Some((t, a)) => Ok(CampaignMapTile::new(t.parse()?, Some(a.parse()?))),
+                None => Ok(CampaignMapTile::new(s.parse()?, None)),
+            }
+        }
+    }
+
     #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum TileOwner{
         Defender,
         Attacker,
@@ -61,6 +292,8 @@ pub mod map_tiles{
 
     /// Campaign Tiles from which the battle map will be generated
     /// Left and right flank tiles are taken from the defender's perspective
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct CampaignGenerationTiles{
         attacker: CampaignMapTile,
         defender: CampaignMapTile,
@@ -71,9 +304,17 @@ pub mod map_tiles{
         mountains_within_search_radius: u32, // for calculating terrain roughness
         hills_within_search_radius: u32, // for calculating terrain roughness
         search_radius: u32,
+        #[cfg_attr(feature = "serde", serde(default = "default_attack_direction"))]
+        attack_direction: HexDirection, // where the attacker's hex lies from the defender's
+    }
+
+    /// Attacks come over the top of the board unless told otherwise
+    fn default_attack_direction() -> HexDirection{
+        HexDirection::UpperRight
     }
 
     impl CampaignGenerationTiles{
+        #[allow(clippy::too_many_arguments)]
         pub fn new(attacker: CampaignMapTile, defender: CampaignMapTile,
              left_flank: CampaignMapTile, right_flank: CampaignMapTile,
             cities: u32, rivers: u32, mountains: u32, hills: u32, search_radius: u32) -> Self{
@@ -86,28 +327,71 @@ pub mod map_tiles{
                     rivers_within_search_radius: rivers,
                     mountains_within_search_radius: mountains,
                     hills_within_search_radius: hills,
-                    search_radius
+                    search_radius,
+                    attack_direction: default_attack_direction(),
                 }
         }
 
-        pub fn default() -> Self{
-            CampaignGenerationTiles { 
-                attacker: CampaignMapTile::default(),
-                defender: CampaignMapTile::default(),
-                left_flank: CampaignMapTile::default(),
-                right_flank: CampaignMapTile::default(),
-                cities_within_search_radius: 0,
-                rivers_within_search_radius: 0,
-                mountains_within_search_radius: 0,
-                hills_within_search_radius: 0,
-                search_radius: 1
+        pub fn get_attacker(&self) -> &CampaignMapTile{
+            &self.attacker
+        }
+
+        pub fn get_defender(&self) -> &CampaignMapTile{
+            &self.defender
+        }
+
+        pub fn get_left_flank(&self) -> &CampaignMapTile{
+            &self.left_flank
+        }
+
+        pub fn get_right_flank(&self) -> &CampaignMapTile{
+            &self.right_flank
+        }
+
+        pub fn get_cities_within_search_radius(&self) -> u32{
+            self.cities_within_search_radius
+        }
+
+        pub fn get_rivers_within_search_radius(&self) -> u32{
+            self.rivers_within_search_radius
+        }
+
+        pub fn get_mountains_within_search_radius(&self) -> u32{
+            self.mountains_within_search_radius
+        }
+
+        pub fn get_hills_within_search_radius(&self) -> u32{
+            self.hills_within_search_radius
+        }
+
+        pub fn get_search_radius(&self) -> u32{
+            self.search_radius
+        }
+
+        /// Direction of the attacker's campaign hex from the defender's, which sets the board edge the attacker holds
+        pub fn set_attack_direction(&mut self, direction: HexDirection){
+            self.attack_direction = direction;
+        }
+
+        pub fn get_attack_direction(&self) -> HexDirection{
+            self.attack_direction
+        }
+
+        /// The campaign tile an owner's region of the board is built from, the split row takes after the attacker
+        pub fn get_owner_tile(&self, owner: &TileOwner) -> &CampaignMapTile{
+            match owner{
+                TileOwner::Attacker | TileOwner::SplitAttDef => &self.attacker,
+                TileOwner::Defender => &self.defender,
+                TileOwner::LeftFlank => &self.left_flank,
+                TileOwner::RightFlank => &self.right_flank,
             }
         }
 
-        /// Calculate the number of hexes within the given radius
-        fn radius_search_size(radius: u32) -> u32{
+        /// Calculate the number of hexes within the given radius, saturating for huge radii
+        pub fn radius_search_size(radius: u32) -> u64{
             // formula is 3r^2 + 3r + 1
-            3 * (radius * radius) + 3 * radius + 1
+            let r = radius as u64;
+            (r * r).saturating_mul(3).saturating_add(3 * r + 1)
         }
 
         /// number of cities / search area
@@ -131,12 +415,30 @@ pub mod map_tiles{
         }
     }
 
+    impl Default for CampaignGenerationTiles{
+        fn default() -> Self{
+            CampaignGenerationTiles { 
+                attacker: CampaignMapTile::default(),
+                defender: CampaignMapTile::default(),
+                left_flank: CampaignMapTile::default(),
+                right_flank: CampaignMapTile::default(),
+                cities_within_search_radius: 0,
+                rivers_within_search_radius: 0,
+                mountains_within_search_radius: 0,
+                hills_within_search_radius: 0,
+                search_radius: 1,
+                attack_direction: default_attack_direction(),
+            }
+        }
+    }
+
     pub trait MapPrint{
         fn map_print(&self) -> ColoredString;
     }
 
     // TODO add any missing tile types that are in the base game
     #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum BattleMapTileType{
         Forest,
         Hill,
@@ -167,53 +469,84 @@ pub mod map_tiles{
         }
     }
 
-    // enum RoadTileType{
-    //     Straight, // has 3 rotations
-    //     Turn120, // has 6 rotations
-    //     BranchY, // has 2 rotations
-    //     Branch120, // has 6 rotations
-    // }
+    /// Fortifications built on a battle tile
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum MapAddOn{
+        Barricade,
+        Palisade,
+        Trench,
+        Wall,
+    }
 
-    // enum RiverTileType{
-    //     Straight, // has 3 rotations
-    //     Turn120, // has 6 rotations
-    //     BranchY, // has 2 rotations
-    //     Branch120, // has 6 rotations
-    //     Lake, // has 6 rotations
-    // }
+    impl MapPrint for MapAddOn{
+        fn map_print(&self) -> ColoredString{
+            match self{
+                MapAddOn::Barricade => String::from("x").black().on_red(),
+                MapAddOn::Palisade => String::from("p").black().on_red(),
+                MapAddOn::Trench => String::from("t").black().on_red(),
+                MapAddOn::Wall => String::from("W").black().on_red(),
+            }
+        }
+    }
 
-    // enum MapAddOn{
-    //     Barricade,
-    // }
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum RiverCrossing{
+        Ford,
+        BridgeStone,
+        BridgeWood
+    }
 
-    // enum RiverCrossing{
-    //     Ford,
-    //     BridgeStone,
-    //     BridgeWood
-    // }
+    impl MapPrint for RiverCrossing{
+        fn map_print(&self) -> ColoredString{
+            match self{
+                RiverCrossing::Ford => String::from("f").black().on_cyan(),
+                RiverCrossing::BridgeStone => String::from("B").black().on_cyan(),
+                RiverCrossing::BridgeWood => String::from("b").black().on_cyan(),
+            }
+        }
+    }
 
     #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct MapTile{
+        #[cfg_attr(feature = "serde", serde(rename = "tile_type"))]
         t_type: BattleMapTileType,
-        owner: TileOwner
-        // add_on: Option<MapAddOn>,
-        // crossing: Option<RiverCrossing>,
+        owner: TileOwner,
+        #[cfg_attr(feature = "serde", serde(default))]
+        add_on: Option<MapAddOn>,
+        crossing: Option<RiverCrossing>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        elevation: u32,
     }
 
-    impl MapTile{
-        pub fn default() -> Self{
+    impl Default for MapTile{
+        fn default() -> Self{
             Self { 
                 t_type: BattleMapTileType::Default,
-                owner: TileOwner::Attacker
+                owner: TileOwner::Attacker,
+                add_on: None,
+                crossing: None,
+                elevation: 0,
              }
         }
+    }
 
+    impl MapTile{
         pub fn new(t_type: BattleMapTileType) -> Self{
-            Self { t_type, owner: TileOwner::Attacker }
+            Self { t_type, owner: TileOwner::Attacker, add_on: None, crossing: None, elevation: 0 }
         }
 
+        /// The tile's type, or its fortification or river crossing if it has one,
+        /// a fortified crossing keeps its glyph in the fortification's colours
         pub fn tile_type_string(&self) -> ColoredString{
-            self.t_type.map_print()
+            match (&self.add_on, &self.crossing){
+                (Some(_), Some(c)) => c.map_print().black().on_red(),
+                (Some(a), None) => a.map_print(),
+                (None, Some(c)) => c.map_print(),
+                (None, None) => self.t_type.map_print(),
+            }
         }
 
         pub fn tile_owner_string(&self) -> ColoredString{
@@ -232,6 +565,35 @@ pub mod map_tiles{
             &self.t_type
         }
 
+        pub fn set_type(&mut self, t_type: BattleMapTileType){
+            self.t_type = t_type;
+        }
+
+        pub fn get_crossing(&self) -> Option<&RiverCrossing>{
+            self.crossing.as_ref()
+        }
+
+        pub fn set_crossing(&mut self, crossing: Option<RiverCrossing>){
+            self.crossing = crossing;
+        }
+
+        pub fn get_add_on(&self) -> Option<&MapAddOn>{
+            self.add_on.as_ref()
+        }
+
+        pub fn set_add_on(&mut self, add_on: Option<MapAddOn>){
+            self.add_on = add_on;
+        }
+
+        /// Height of the ground in levels, 0 being the lowest ground on the map
+        pub fn get_elevation(&self) -> u32{
+            self.elevation
+        }
+
+        pub fn set_elevation(&mut self, elevation: u32){
+            self.elevation = elevation;
+        }
+
     }
 
 }
@@ -239,9 +601,192 @@ pub mod map_tiles{
 
 pub mod battle_map{
 
-    use crate::map_tiles::{MapTile, CampaignGenerationTiles, TileOwner, BattleMapTileType};
+    use crate::map_tiles::{normalize_name, MapTile, CampaignGenerationTiles, CampaignMapTile, TileOwner, BattleMapTileType, MapAddOn, RiverCrossing};
+    use crate::hex_paths::HexNetwork;
+    use crate::coords::{HexCoord, MapLocation};
+    use crate::errors::MapGenError;
+    use crate::deployment::DeploymentLayout;
+    use crate::{clusters, elevation, fortifications, outposts, rivers, roads, towns};
+    use crate::wfc::{self, WfcSolver};
     use colored::ColoredString;
-    use rand::{Rng};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use std::fmt;
+    use std::fmt::Write as _;
+    use std::str::FromStr;
+
+    /// Number of tiles a density covers on a board of `total_tiles`
+    fn density_count(density: f64, total_tiles: u32) -> u32{
+        (density * total_tiles as f64) as u32
+    }
+
+    /// Boards must be at least 4w x 2h and hold no more than `MAX_BOARD_TILES` tiles
+    fn check_board_size(width: usize, height: usize) -> Result<(), MapGenError>{
+        if width < 4 || height < 2{
+            return Err(MapGenError::BoardTooSmall { width, height });
+        }
+        match width.checked_mul(height).is_some_and(|n| n <= MAX_BOARD_TILES){
+            true => Ok(()),
+            false => Err(MapGenError::BoardTooLarge { width, height }),
+        }
+    }
+
+    /// A random index below `len`, drawn the same way on every platform
+    /// so a seed produces the same map on 32 and 64 bit targets
+    pub(crate) fn random_index<R: Rng + ?Sized>(gen: &mut R, len: usize) -> usize{
+        gen.gen_range(0..len as u64) as usize
+    }
+
+    /// The six directions out of a hex, in clockwise order starting from the right
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum HexDirection{
+        Right,
+        LowerRight,
+        LowerLeft,
+        Left,
+        UpperLeft,
+        UpperRight
+    }
+
+    impl HexDirection{
+        pub const ALL: [HexDirection; 6] = [
+            HexDirection::Right, HexDirection::LowerRight, HexDirection::LowerLeft,
+            HexDirection::Left, HexDirection::UpperLeft, HexDirection::UpperRight
+        ];
+
+        /// Position of the direction in clockwise order, Right is 0
+        pub fn index(&self) -> usize{
+            match self{
+                HexDirection::Right => 0,
+                HexDirection::LowerRight => 1,
+                HexDirection::LowerLeft => 2,
+                HexDirection::Left => 3,
+                HexDirection::UpperLeft => 4,
+                HexDirection::UpperRight => 5,
+            }
+        }
+
+        pub fn opposite(&self) -> HexDirection{
+            HexDirection::ALL[(self.index() + 3) % 6]
+        }
+
+        /// Number of 60 degree turns between two directions, 0 to 3
+        pub fn turn_steps(&self, other: HexDirection) -> usize{
+            let diff = (self.index() + 6 - other.index()) % 6;
+            diff.min(6 - diff)
+        }
+    }
+
+    impl fmt::Display for HexDirection{
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            let s = match self{
+                HexDirection::Right => "Right",
+                HexDirection::LowerRight => "LowerRight",
+                HexDirection::LowerLeft => "LowerLeft",
+                HexDirection::Left => "Left",
+                HexDirection::UpperLeft => "UpperLeft",
+                HexDirection::UpperRight => "UpperRight",
+            };
+            write!(f, "{}", s)
+        }
+    }
+
+    impl FromStr for HexDirection{
+        type Err = MapGenError;
+
+        /// Parse a direction, ignoring case and any '_', '-' or ' ', e.g. "upper-right" or "UpperRight"
+        fn from_str(s: &str) -> Result<Self, Self::Err>{
+            match normalize_name(s).as_str(){
+                "right" => Ok(HexDirection::Right),
+                "lowerright" => Ok(HexDirection::LowerRight),
+                "lowerleft" => Ok(HexDirection::LowerLeft),
+                "left" => Ok(HexDirection::Left),
+                "upperleft" => Ok(HexDirection::UpperLeft),
+                "upperright" => Ok(HexDirection::UpperRight),
+                _ => Err(MapGenError::Parse(format!("Unknown hex direction: {}", s))),
+            }
+        }
+    }
+
+    /// The four sides of the battle map
+    /// The attacker enters over the edge its attack comes from and the defender holds the opposite one,
+    /// by default the attacker enters from the top and the defender holds the bottom
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum BoardEdge{
+        Top,
+        Bottom,
+        Left,
+        Right
+    }
+
+    impl BoardEdge{
+        pub const ALL: [BoardEdge; 4] = [BoardEdge::Top, BoardEdge::Bottom, BoardEdge::Left, BoardEdge::Right];
+
+        pub fn opposite(&self) -> BoardEdge{
+            match self{
+                BoardEdge::Top => BoardEdge::Bottom,
+                BoardEdge::Bottom => BoardEdge::Top,
+                BoardEdge::Left => BoardEdge::Right,
+                BoardEdge::Right => BoardEdge::Left,
+            }
+        }
+
+        /// Position clockwise from the top
+        fn clockwise_index(&self) -> usize{
+            match self{
+                BoardEdge::Top => 0,
+                BoardEdge::Right => 1,
+                BoardEdge::Bottom => 2,
+                BoardEdge::Left => 3,
+            }
+        }
+
+        /// The edge an attack from `direction` comes over, the nearest of the four sides,
+        /// so attacks from the upper left and upper right both come over the top
+        pub fn from_direction(direction: HexDirection) -> BoardEdge{
+            match direction{
+                HexDirection::Right => BoardEdge::Right,
+                HexDirection::Left => BoardEdge::Left,
+                HexDirection::UpperLeft | HexDirection::UpperRight => BoardEdge::Top,
+                HexDirection::LowerLeft | HexDirection::LowerRight => BoardEdge::Bottom,
+            }
+        }
+
+        /// Side whose campaign tile lies beyond this edge when the attack comes from `attack`,
+        /// clockwise from the attacker's edge lie the right flank, the defender and the left flank
+        /// This follows the campaign map and not the deployment layout, so under encirclement or a custom mask
+        /// the hexes along the edge may belong to another side, see `Map::edge_of_owner`
+        pub fn owner(&self, attack: HexDirection) -> TileOwner{
+            match (self.clockwise_index() + 4 - BoardEdge::from_direction(attack).clockwise_index()) % 4{
+                0 => TileOwner::Attacker,
+                1 => TileOwner::RightFlank,
+                2 => TileOwner::Defender,
+                _ => TileOwner::LeftFlank,
+            }
+        }
+
+        /// Edge of the board facing an owner's campaign tile when the attack comes from `attack`, None for the split row
+        pub fn of_owner(owner: &TileOwner, attack: HexDirection) -> Option<BoardEdge>{
+            BoardEdge::ALL.iter().copied().find(|e| e.owner(attack) == *owner)
+        }
+
+        /// The campaign tile lying beyond this edge of the battle map
+        pub fn campaign_tile<'a>(&self, tiles: &'a CampaignGenerationTiles) -> &'a CampaignMapTile{
+            tiles.get_owner_tile(&self.owner(tiles.get_attack_direction()))
+        }
+
+        /// Directions that can point off the board across this edge
+        pub fn exit_directions(&self) -> &'static [HexDirection]{
+            match self{
+                BoardEdge::Top => &[HexDirection::UpperLeft, HexDirection::UpperRight],
+                BoardEdge::Bottom => &[HexDirection::LowerLeft, HexDirection::LowerRight],
+                BoardEdge::Left => &[HexDirection::Left, HexDirection::UpperLeft, HexDirection::LowerLeft],
+                BoardEdge::Right => &[HexDirection::Right, HexDirection::UpperRight, HexDirection::LowerRight],
+            }
+        }
+    }
     
     pub struct TileNeighbors<'a>{
         tile_location: (usize, usize),
@@ -255,6 +800,7 @@ pub mod battle_map{
     }
 
     impl<'a> TileNeighbors<'a>{
+        #[allow(clippy::too_many_arguments)]
         fn new(tile_location: (usize, usize), tile: &'a MapTile, 
         left: Option<&'a MapTile>, upper_left: Option<&'a MapTile>, upper_right: Option<&'a MapTile>, 
         right: Option<&'a MapTile>, lower_right: Option<&'a MapTile>, lower_left: Option<&'a MapTile>) -> Self{
@@ -301,198 +847,488 @@ pub mod battle_map{
 
     }
 
+    /// Most tiles a generated board may have, larger boards are refused rather than exhausting memory
+    pub const MAX_BOARD_TILES: usize = 1_000_000;
+
+    /// How strongly each owner's region resembles its campaign tile when not otherwise specified
+    pub const DEFAULT_CAMPAIGN_SIMILARITY: f64 = 0.6;
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct MapGenerator{
         base_tiles: CampaignGenerationTiles,
+        #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_similarity"))]
+        campaign_similarity: f64, // chance a free tile takes its owner's campaign terrain
+        seed: Option<u64>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        layout: DeploymentLayout,
+        #[cfg_attr(feature = "serde", serde(default))]
+        wfc_terrain: bool, // fill the free ground by wave function collapse instead of by sector
+    }
+
+    /// Read a campaign similarity, clamped like `set_campaign_similarity`
+    #[cfg(feature = "serde")]
+    fn deserialize_similarity<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error>{
+        let similarity = <f64 as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(clamp_similarity(similarity))
+    }
+
+    /// Keep a similarity within 0.0..=1.0, falling back to the default when it is not a number
+    fn clamp_similarity(similarity: f64) -> f64{
+        match similarity.is_nan(){
+            true => DEFAULT_CAMPAIGN_SIMILARITY,
+            false => similarity.clamp(0.0, 1.0),
+        }
+    }
+
+    impl Default for MapGenerator{
+        fn default() -> Self{
+            MapGenerator::new(CampaignGenerationTiles::default())
+        }
     }
 
     impl MapGenerator
     {
         pub fn new(base_tiles: CampaignGenerationTiles) -> Self {
             MapGenerator{
-                base_tiles
+                base_tiles,
+                campaign_similarity: DEFAULT_CAMPAIGN_SIMILARITY,
+                seed: None,
+                layout: DeploymentLayout::default(),
+                wfc_terrain: false,
             }
         }
 
-        pub fn default() -> Self{
-            MapGenerator{
-                base_tiles: CampaignGenerationTiles::default()
-            }
+        /// Generate every map from this seed, the same seed, campaign tiles and size always give the same map
+        /// Without a seed a random one is chosen for each map and stored on it
+        pub fn set_seed(&mut self, seed: u64){
+            self.seed = Some(seed);
+        }
+
+        pub fn get_seed(&self) -> Option<u64>{
+            self.seed
+        }
+
+        /// Set how similar each owned region should be to its campaign tile, clamped to 0.0..=1.0
+        /// 0.0 ignores the campaign tiles, 1.0 fills every free tile with the campaign terrain
+        pub fn set_campaign_similarity(&mut self, similarity: f64){
+            self.campaign_similarity = clamp_similarity(similarity);
+        }
+
+        pub fn get_campaign_similarity(&self) -> f64{
+            self.campaign_similarity
+        }
+
+        /// Set how the board is split between the attacker, defender and flanks
+        pub fn set_layout(&mut self, layout: DeploymentLayout){
+            self.layout = layout;
+        }
+
+        pub fn get_layout(&self) -> &DeploymentLayout{
+            &self.layout
+        }
+
+        /// Fill the free ground from `wfc::battle_terrain_rules`, fitting plains, forests and swamps around
+        /// the rivers, roads, settlements and high ground already placed, instead of growing each owner's
+        /// campaign terrain in its own sector
+        pub fn set_wfc_terrain(&mut self, wfc_terrain: bool){
+            self.wfc_terrain = wfc_terrain;
         }
 
+        pub fn get_wfc_terrain(&self) -> bool{
+            self.wfc_terrain
+        }
 
         /// Create the default map size (15w x 11h)
         pub fn create_default_map(&self) -> Map{
             self.create_map(15, 11)
         }
 
-        // TODO: add parameters to initialize MapTiles
+        /// Create and generate a map, panics if the board is too small or too dense to generate,
+        /// see `try_create_map`
         pub fn create_map(&self, board_width: usize, board_height: usize) -> Map{
+            self.try_create_map(board_width, board_height).unwrap_or_else(|e| panic!("{}", e))
+        }
+
+        /// Create and generate a map, failing if the board is below 4w x 2h or above `MAX_BOARD_TILES`, the campaign
+        /// densities ask for more towns, mountains and hills than the board has tiles,
+        /// or the deployment layout does not fit the board or leaves an owner without tiles
+        pub fn try_create_map(&self, board_width: usize, board_height: usize) -> Result<Map, MapGenError>{
+            let tiles = self.create_empty_board(board_width, board_height)?;
+            let owners = self.layout.owners(board_width, board_height, self.base_tiles.get_attack_direction())?;
+            let total_tiles = (board_width * board_height) as u64;
+            let requested = [self.base_tiles.city_density(), self.base_tiles.mountain_density(), self.base_tiles.hill_density()]
+                .iter()
+                .map(|d| density_count(*d, total_tiles as u32) as u64)
+                .sum();
+            if requested > total_tiles{
+                return Err(MapGenError::DensityTooHigh { requested, available: total_tiles });
+            }
+
             let mut m = Map { 
-                tiles: self.create_empty_board(board_width, board_height), 
-                board_height, board_width
+                tiles, 
+                board_height, board_width,
+                seed: self.seed.unwrap_or_else(rand::random),
+                base_tiles: self.base_tiles.clone(),
+                layout: self.layout.clone(),
+                rivers: HexNetwork::new(),
+                roads: HexNetwork::new(),
                 };
 
-                self.set_tile_owners(&mut m);
-                self.generate_map_tiles(&mut m);
-                m
+            for (t, owner) in m.tiles.iter_mut().zip(owners){
+                t.set_owner(owner);
+            }
+            self.generate_map_tiles(&mut m)?;
+            Ok(m)
         }
 
         /// Create an empty board based on the widths and heights passed
-        fn create_empty_board(&self, width: usize, height: usize) -> Vec<MapTile>{
-            // must be at least 2x2
-            assert!(width >= 4, "Must be at least 4 wide");
-            assert!(height >= 2, "Must be at least 2 high");
-
-            vec![MapTile::default(); width * height]
+        fn create_empty_board(&self, width: usize, height: usize) -> Result<Vec<MapTile>, MapGenError>{
+            check_board_size(width, height)?;
+            Ok(vec![MapTile::default(); width * height])
         }
 
-        fn generate_map_tiles(&self, map: &mut Map){
+        /// Fails if the layout leaves an owner without tiles once the rivers are in place
+        fn generate_map_tiles(&self, map: &mut Map) -> Result<(), MapGenError>{
             // calculate total tiles
             let total_tiles: u32 = map.board_height as u32 * map.board_width as u32;
 
             // determine town count
-            let town_ct: u32 = (self.base_tiles.city_density() * total_tiles as f64) as u32;
+            let town_ct: u32 = density_count(self.base_tiles.city_density(), total_tiles);
 
             // determine river count
-            let river_ct: u32 = (self.base_tiles.river_density() * total_tiles as f64) as u32;
+            let river_ct: u32 = density_count(self.base_tiles.river_density(), total_tiles);
 
             // determine mountain count
-            let mtn_ct: u32 = (self.base_tiles.mountain_density() * total_tiles as f64) as u32;
+            let mtn_ct: u32 = density_count(self.base_tiles.mountain_density(), total_tiles);
 
             // determine hill count
-            let hill_ct: u32 = (self.base_tiles.hill_density() * total_tiles as f64) as u32;
+            let hill_ct: u32 = density_count(self.base_tiles.hill_density(), total_tiles);
 
-            // determine which edges any roads should enter/exit on -> WFC?
-
-            // determine which edges any rivers should enter/exit on -> WFC?
-
-
-            // TODO add better terrain generation
-            // randomly place the town, river, mtn, hill tiles
             let mut default_tiles: Vec<(usize, usize)> = (0..map.board_height)
-                .into_iter()
-                .map(|w| (0..map.board_width)
-                    .into_iter().map(|h| (w,h))
-                    .collect::<Vec<(usize, usize)>>())
-                .flatten()
+                .flat_map(|w| (0..map.board_width).map(move |h| (w,h)))
                 .collect();
-            let mut gen = rand::thread_rng();
-            for _ in 0..hill_ct{
-                let rnd: usize = gen.gen_range(0..default_tiles.len());
-                map.set_tile(default_tiles[rnd].0, default_tiles[rnd].1, MapTile::new(BattleMapTileType::Hill));
-                default_tiles.remove(rnd);
-            }
-            for _ in 0..town_ct{
-                let rnd: usize = gen.gen_range(0..default_tiles.len());
-                map.set_tile(default_tiles[rnd].0, default_tiles[rnd].1, MapTile::new(BattleMapTileType::Town));
-                default_tiles.remove(rnd);
-            }
-            for _ in 0..mtn_ct{
-                let rnd: usize = gen.gen_range(0..default_tiles.len());
-                map.set_tile(default_tiles[rnd].0, default_tiles[rnd].1, MapTile::new(BattleMapTileType::Mountain));
-                default_tiles.remove(rnd);
-            }
-            for _ in 0..river_ct{
-                let rnd: usize = gen.gen_range(0..default_tiles.len());
-                map.set_tile(default_tiles[rnd].0, default_tiles[rnd].1, MapTile::new(BattleMapTileType::River));
-                default_tiles.remove(rnd);
-            }
-            // set remaining default tiles to plains
-            for t in default_tiles.iter(){
-                map.set_tile(t.0, t.1, MapTile::new(BattleMapTileType::Plains))
-            }
+            let mut gen = ChaCha8Rng::seed_from_u64(map.seed);
 
+            // rolling ground, rougher the more mountains and hills are nearby
+            let roughness = elevation::roughness(&self.base_tiles);
+            let heights = elevation::generate_heightmap(map.board_width, map.board_height, roughness, &mut gen);
+            for (i, h) in heights.into_iter().enumerate(){
+                map.tiles[i].set_elevation(h);
+            }
 
+            // rivers enter/exit on the edges whose campaign tiles carry a river, or where the layout wants one,
+            // with no river nearby a stream may still cross between two random edges
+            let attack = self.base_tiles.get_attack_direction();
+            let mut river_edges = self.layout.river_edges(rivers::river_edges(&self.base_tiles), attack);
+            if river_edges.is_empty() && river_ct > 0{
+                let first = BoardEdge::ALL[random_index(&mut gen, BoardEdge::ALL.len())];
+                let others: Vec<BoardEdge> = BoardEdge::ALL.iter().copied().filter(|e| *e != first).collect();
+                river_edges = vec![first, others[random_index(&mut gen, others.len())]];
+            }
+            let (width, height) = (map.board_width, map.board_height);
+            let cost = |l| Some(self.layout.river_cost(width, height, attack, l));
+            map.rivers = rivers::generate_rivers_with(map, &river_edges, cost, &mut gen);
+            for loc in map.rivers.locations(){
+                map.set_tile_type(loc.0, loc.1, BattleMapTileType::River);
+            }
+            let owners = self.layout.finalize(map)?;
+            for (t, owner) in map.tiles.iter_mut().zip(owners){
+                t.set_owner(owner);
+            }
+            default_tiles.retain(|loc| !map.rivers.contains(*loc));
 
-        }
+            // the highest ground becomes mountains and the slopes below them hills
+            self.place_high_ground(map, &mut default_tiles, mtn_ct, BattleMapTileType::Mountain);
+            self.place_high_ground(map, &mut default_tiles, hill_ct, BattleMapTileType::Hill);
 
-        // /// finds a random tile on the map that is the Default type, panics if there are none
-        // fn find_default_tile(map: &Map, default_tiles: &Vec<(usize, usize)>) -> (usize, usize){
-        //     (0, 0)
-        // }
+            // roads run between the edges whose campaign tiles carry a road
+            let road_edges = roads::road_edges(&self.base_tiles);
+            let mut road_network = roads::generate_roads(map, &road_edges, &mut gen);
 
-        fn set_tile_owners(&self, map: &mut Map){
-            let flank_width = map.board_width / 4;
-            let vertical_owner_depth = map.board_height / 2;
-            
-            // set flank owners
-            for v in 0..map.board_height{
-                for t in map.tiles.iter_mut().skip(v * map.board_width).take(flank_width){
-                    t.set_owner(TileOwner::LeftFlank);
+            // outposts hold the high ground of their owner's region, with a road off the map
+            for owner in outposts::outpost_owners(&self.base_tiles){
+                if let Some(outpost) = outposts::place_outpost(map, &owner){
+                    map.set_tile_type(outpost.0, outpost.1, BattleMapTileType::Outpost);
+                    default_tiles.retain(|l| *l != outpost);
+                    outposts::connect_outpost(map, &mut road_network, outpost, &owner, &mut gen);
                 }
+            }
 
-                for t in map.tiles.iter_mut().skip((v + 1) * map.board_width - flank_width).take(flank_width){
-                    t.set_owner(TileOwner::RightFlank);
-                }
+            // towns settle on flat ground by the roads and rivers, each joined to the road network
+            let settlements = towns::place_towns(
+                map,
+                &default_tiles,
+                &towns::town_owners(&self.base_tiles),
+                town_ct as usize,
+                towns::town_size(self.base_tiles.city_density()),
+                &road_network,
+                &mut gen,
+            );
+            for hex in settlements.iter().flatten(){
+                map.set_tile_type(hex.0, hex.1, BattleMapTileType::Town);
             }
+            let centers: Vec<(usize, usize)> = settlements.iter().map(|s| s[0]).collect();
+            roads::connect_locations(map, &mut road_network, &centers, &mut gen);
+            default_tiles.retain(|l| !road_network.contains(*l) && !settlements.iter().flatten().any(|t| t == l));
+            map.roads = road_network;
 
-            // set attacker owners
-            for v in 0..vertical_owner_depth{
-                for t in map.tiles.iter_mut().skip(v * map.board_width + flank_width).take(map.board_width - (2 * flank_width)){
-                    t.set_owner(TileOwner::Attacker);
-                }
+            // fill the remaining tiles from the terrain rules, or bias them towards their owner's campaign tile
+            if !(self.wfc_terrain && self.generate_wfc_terrain(map, &mut default_tiles, &mut gen)){
+                self.generate_sector_terrain(map, &mut default_tiles, &mut gen);
             }
 
-            // set defender owners
-            for v in (map.board_height - vertical_owner_depth)..map.board_height{
-                for t in map.tiles.iter_mut().skip(v * map.board_width + flank_width).take(map.board_width - (2 * flank_width)){
-                    t.set_owner(TileOwner::Defender);
-                }
+            // set remaining default tiles to plains
+            for t in default_tiles.iter(){
+                map.set_tile_type(t.0, t.1, BattleMapTileType::Plains)
             }
 
-            // set split owners if odd height
-            if map.board_height % 2 == 1{
-                for t in  map.tiles.iter_mut().skip(vertical_owner_depth * map.board_width + flank_width).take(map.board_width - (2 * flank_width)){
-                    t.set_owner(TileOwner::SplitAttDef);
+            // road hexes take their type once the terrain around them is in place
+            for loc in map.roads.locations(){
+                if map.get_tile(loc.0, loc.1).is_some_and(|t| !roads::keeps_type_under_road(t.get_type())){
+                    map.set_tile_type(loc.0, loc.1, BattleMapTileType::Road);
                 }
             }
 
+            // bridges where roads cross rivers, fords at shallow points
+            for (loc, crossing) in rivers::generate_crossings(map, &self.base_tiles, &mut gen){
+                map.set_tile_crossing(loc.0, loc.1, Some(crossing));
+            }
 
+            // the defender digs in at chokepoints and along the roads
+            for (loc, add_on) in fortifications::fortify(map, &self.base_tiles, &mut gen){
+                map.set_tile_add_on(loc.0, loc.1, Some(add_on));
+            }
+            for (loc, add_on) in outposts::outpost_defences(map){
+                map.set_tile_add_on(loc.0, loc.1, Some(add_on));
+            }
+            Ok(())
         }
 
+        /// Turn free tiles at or above the elevation threshold closest to giving `count` tiles into `t_type`
+        fn place_high_ground(&self, map: &mut Map, default_tiles: &mut Vec<(usize, usize)>, count: u32, t_type: BattleMapTileType){
+            let heights: Vec<u32> = default_tiles.iter()
+                .map(|&(r, c)| map.get_tile(r, c).expect("Default tiles must be on the board").get_elevation())
+                .collect();
+            let Some(threshold) = elevation::threshold_for_count(&heights, count as usize) else { return };
+            let mut remaining = vec![];
+            for (&(row, column), h) in default_tiles.iter().zip(heights){
+                match h >= threshold{
+                    true => map.set_tile_type(row, column, t_type.clone()),
+                    false => remaining.push((row, column)),
+                }
+            }
+            *default_tiles = remaining;
+        }
+
+        /// Fill free tiles with the terrain of the campaign tile owning their sector,
+        /// the campaign similarity is the share of each sector filled
+        /// Forests and swamps grow in clusters, swamps on low ground beside rivers,
+        /// and hills and mountains take the sector's highest ground
+        fn generate_sector_terrain<R: Rng>(&self, map: &mut Map, default_tiles: &mut Vec<(usize, usize)>, gen: &mut R){
+            // group the free tiles by the campaign tile they take after
+            let mut sectors: Vec<(TileOwner, Vec<(usize, usize)>)> = vec![];
+            for &(row, column) in default_tiles.iter(){
+                let owner = map.get_tile(row, column).expect("Default tiles must be on the board").get_owner();
+                let owner = self.sector_owner(owner, gen);
+                match sectors.iter_mut().find(|(o, _)| *o == owner){
+                    Some(sector) => sector.1.push((row, column)),
+                    None => sectors.push((owner, vec![(row, column)])),
+                }
+            }
+
+            let top = map.tiles.iter().map(|t| t.get_elevation()).max().unwrap_or(0);
+            let mut filled = vec![false; map.tiles.len()];
+            for (owner, tiles) in sectors{
+                let Some(terrain) = self.base_tiles.get_owner_tile(&owner).get_type().battle_terrain() else { continue };
+                let count = (density_count(self.campaign_similarity, tiles.len() as u32) as usize).min(tiles.len());
+                let chosen = match terrain{
+                    BattleMapTileType::Forest => clusters::grow_clusters(map, &tiles, count, |_| 1, gen),
+                    BattleMapTileType::Swamp => clusters::grow_clusters(map, &tiles, count, |l| clusters::swamp_weight(map, l, top), gen),
+                    BattleMapTileType::Hill | BattleMapTileType::Mountain => {
+                        let mut highest = tiles.clone();
+                        highest.sort_by_key(|l| std::cmp::Reverse(map.get_tile(l.0, l.1).map_or(0, |t| t.get_elevation())));
+                        highest.truncate(count);
+                        highest
+                    },
+                    _ => {
+                        let mut rest = tiles.clone();
+                        (0..count).map(|_| rest.swap_remove(random_index(gen, rest.len()))).collect()
+                    },
+                };
+                for l in chosen{
+                    map.set_tile_type(l.0, l.1, terrain.clone());
+                    filled[l.0 * map.board_width + l.1] = true;
+                }
+            }
+            default_tiles.retain(|l| !filled[l.0 * map.board_width + l.1]);
+        }
+
+        /// Fill free tiles with plains, forest or swamp by wave function collapse, every other hex restricted
+        /// to what is already placed there and road hexes to roads
+        /// The campaign terrain is favoured across the whole board by the campaign similarity
+        /// Returns false, leaving the map as it was, if the rules cannot be met
+        fn generate_wfc_terrain<R: Rng>(&self, map: &mut Map, default_tiles: &mut Vec<(usize, usize)>, gen: &mut R) -> bool{
+            let mut rules = wfc::battle_terrain_rules();
+            for owner in [TileOwner::Attacker, TileOwner::Defender, TileOwner::LeftFlank, TileOwner::RightFlank]{
+                let Some(terrain) = self.base_tiles.get_owner_tile(&owner).get_type().battle_terrain() else { continue };
+                if let Some(weight) = rules.get_weight(&terrain){
+                    rules.add_tile(terrain, weight + 2.5 * self.campaign_similarity).expect("Updating a weight never adds a tile");
+                }
+            }
+
+            let mut free = vec![false; map.tiles.len()];
+            for l in default_tiles.iter(){
+                free[l.0 * map.board_width + l.1] = true;
+            }
+            let open_ground = [BattleMapTileType::Plains, BattleMapTileType::Forest, BattleMapTileType::Swamp];
+            let mut solver = WfcSolver::new(rules);
+            for (i, t) in map.tiles.iter().enumerate(){
+                let allowed = match (free[i], t.get_type()){
+                    (true, _) => open_ground.to_vec(),
+                    (false, BattleMapTileType::Default) => vec![BattleMapTileType::Road],
+                    (false, t_type) => vec![t_type.clone()],
+                };
+                if solver.restrict(map, (i / map.board_width, i % map.board_width), &allowed).is_err(){
+                    return false;
+                }
+            }
+            let Some(tiles) = solver.solve(map, gen.gen()) else { return false };
+            for l in default_tiles.drain(..){
+                map.set_tile_type(l.0, l.1, tiles[l.0 * map.board_width + l.1].clone());
+            }
+            true
+        }
 
+        /// The owner whose campaign tile a tile owned by `owner` takes after,
+        /// the split row takes from either the attacker or defender with equal chance
+        fn sector_owner<R: Rng>(&self, owner: &TileOwner, gen: &mut R) -> TileOwner{
+            match owner{
+                TileOwner::SplitAttDef => match gen.gen_bool(0.5){
+                    true => TileOwner::Attacker,
+                    false => TileOwner::Defender,
+                },
+                o => o.clone(),
+            }
+        }
 
-
-
+        // /// finds a random tile on the map that is the Default type, panics if there are none
+        // fn find_default_tile(map: &Map, default_tiles: &Vec<(usize, usize)>) -> (usize, usize){
+        //     (0, 0)
+        // }
     }
 
 
 
+    /// Version of the JSON written by `Map::to_json`, bumped whenever the format changes
+    #[cfg(feature = "serde")]
+    pub const MAP_JSON_VERSION: u32 = 1;
+
+    #[cfg(feature = "serde")]
+    #[derive(serde::Serialize)]
+    struct MapDocumentRef<'a>{
+        version: u32,
+        #[serde(flatten)]
+        map: &'a Map,
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(serde::Deserialize)]
+    struct MapDocument{
+        version: u32,
+        #[serde(flatten)]
+        map: Map,
+    }
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Map{
         tiles: Vec<MapTile>,
         board_height: usize,
         board_width: usize,
+        seed: u64,
+        #[cfg_attr(feature = "serde", serde(rename = "campaign_tiles"))]
+        base_tiles: CampaignGenerationTiles,
+        #[cfg_attr(feature = "serde", serde(default))]
+        layout: DeploymentLayout,
+        rivers: HexNetwork,
+        roads: HexNetwork,
+    }
+
+    #[cfg(feature = "serde")]
+    impl Map{
+        /// Write the map as versioned JSON
+        pub fn to_json(&self) -> String{
+            serde_json::to_string_pretty(&MapDocumentRef { version: MAP_JSON_VERSION, map: self })
+                .expect("Maps always serialize")
+        }
+
+        /// Read a map written by `to_json`, checking the version and that the
+        /// tiles, rivers and roads all fit the stored dimensions
+        pub fn from_json(json: &str) -> Result<Map, MapGenError>{
+            let doc: MapDocument = serde_json::from_str(json).map_err(|e| MapGenError::InvalidData(e.to_string()))?;
+            if doc.version != MAP_JSON_VERSION{
+                return Err(MapGenError::UnsupportedVersion { found: doc.version, expected: MAP_JSON_VERSION });
+            }
+            let map = doc.map;
+            check_board_size(map.board_width, map.board_height)?;
+            if map.tiles.len() != map.board_width * map.board_height{
+                return Err(MapGenError::TileCountMismatch { expected: map.board_width * map.board_height, found: map.tiles.len() });
+            }
+            for network in [&map.rivers, &map.roads]{
+                if let Some(l) = network.locations().into_iter().find(|l| map.get_tile(l.0, l.1).is_none()){
+                    return Err(map.out_of_bounds(l.0, l.1));
+                }
+            }
+            Ok(map)
+        }
     }
 
     impl Map{
         pub fn print_board_tiles(&self){
-            self.print_board(&self.tiles.iter().map(|i| i.tile_type_string()).collect::<Vec<ColoredString>>())
+            print!("{}", self.board_tiles_string(true))
         }
 
         pub fn print_board_owners(&self){
-            self.print_board(&self.tiles.iter().map(|i| i.tile_owner_string()).collect::<Vec<ColoredString>>())
+            print!("{}", self.board_owners_string(true))
+        }
+
+        /// The board drawn as text with each tile's type, as printed by `print_board_tiles`
+        pub fn board_tiles_string(&self, colored: bool) -> String{
+            self.board_string(&self.tiles.iter().map(|i| i.tile_type_string()).collect::<Vec<ColoredString>>(), colored)
         }
 
-        /// Print board to console
-        fn print_board(&self, tile_abbrs: &[ColoredString]){
+        /// The board drawn as text with each tile's owner, as printed by `print_board_owners`
+        pub fn board_owners_string(&self, colored: bool) -> String{
+            self.board_string(&self.tiles.iter().map(|i| i.tile_owner_string()).collect::<Vec<ColoredString>>(), colored)
+        }
+
+        /// Draw board as text, with or without the terminal colours
+        fn board_string(&self, tile_abbrs: &[ColoredString], colored: bool) -> String{
+            let tile_abbrs: Vec<String> = tile_abbrs.iter()
+                .map(|a| match colored{
+                    true => a.to_string(),
+                    false => String::from(&**a),
+                })
+                .collect();
+            let mut out = String::new();
             // use /,\,_,| to create board
-            println!("Board: {}w x {}h", self.board_width, self.board_height);
+            let _ = writeln!(out, "Board: {}w x {}h", self.board_width, self.board_height);
 
             let mut j = 0;
             while j < self.board_height{
                 // print top of even row
-                print!(" ");
+                let _ = write!(out, " ");
                 for _ in 0..self.board_width{
-                    print!("/ \\_")
+                    let _ = write!(out, "/ \\_");
                 }
-                println!("/");
+                let _ = writeln!(out, "/");
 
                 // print even row
                 for i in 0..self.board_width{
                     assert!(j % 2 == 0);
-                    print!("| {} ", tile_abbrs[j * self.board_width + i]);
+                    let _ = write!(out, "| {} ", tile_abbrs[j * self.board_width + i]);
                 }
-                println!("|");
+                let _ = writeln!(out, "|");
                 j += 1;
                 
                 if j >= self.board_height{
@@ -501,43 +1337,49 @@ pub mod battle_map{
 
                 // print bottom of even row
                 for _ in 0..self.board_width{
-                    print!(" \\_/");
+                    let _ = write!(out, " \\_/");
                 }
-                println!(" \\");
+                let _ = writeln!(out, " \\");
 
                 // print odd row
                 assert!(j % 2 == 1);
-                print!(" ");
+                let _ = write!(out, " ");
                 for i in 0..self.board_width{ // odd row
-                    print!(" | {}", tile_abbrs[j * self.board_width + i])
+                    let _ = write!(out, " | {}", tile_abbrs[j * self.board_width + i]);
                 }
      
-                println!(" |");
+                let _ = writeln!(out, " |");
                 j += 1;
 
             }
             
             // print bottom row
             if self.board_height % 2 != 0{
-                print!(" \\");
+                let _ = write!(out, " \\");
             }
             for _ in 0..self.board_width-1{
-                print!(" / \\");
+                let _ = write!(out, " / \\");
             }
-            print!(" / \\");
+            let _ = write!(out, " / \\");
             if self.board_height % 2 == 0{
-                print!(" /");
+                let _ = write!(out, " /");
             }
 
-            println!();
-            println!();
-
+            let _ = writeln!(out);
+            let _ = writeln!(out);
+            out
         }
 
         /// Get the neighbors of a given hex
-        pub fn get_neighbors(&self, row: usize, column: usize) -> TileNeighbors{
+        /// Panics if the tile is not on the board, see `try_get_neighbors`
+        pub fn get_neighbors(&self, row: usize, column: usize) -> TileNeighbors<'_>{
+            self.try_get_neighbors(row, column).unwrap_or_else(|e| panic!("Tile must exist to get neighbors: {}", e))
+        }
+
+        /// Get the neighbors of a given hex, failing if it is not on the board
+        pub fn try_get_neighbors(&self, row: usize, column: usize) -> Result<TileNeighbors<'_>, MapGenError>{
 
-            let tile = self.get_tile(row, column).expect("Tile must exist to get neighbors");
+            let tile = self.get_tile(row, column).ok_or_else(|| self.out_of_bounds(row, column))?;
             
             // right
             let right = self.get_tile(row, column+1);
@@ -588,19 +1430,228 @@ pub mod battle_map{
                 }
             };
             
-            TileNeighbors::new((row, column), tile, left, upper_left, upper_right, right, lower_right, lower_left)
+            Ok(TileNeighbors::new((row, column), tile, left, upper_left, upper_right, right, lower_right, lower_left))
 
         }
 
         /// Get a reference to a tile from the board
         pub fn get_tile(&self, row: usize, column: usize) -> Option<&MapTile>{
+            if row >= self.board_height || column >= self.board_width{
+                return None;
+            }
             self.tiles.get(row * self.board_width + column)
         }
 
-        /// Set a tile
+        /// Get a tile by offset (row, column) or `HexCoord`
+        pub fn get_tile_at<L: MapLocation>(&self, location: L) -> Option<&MapTile>{
+            location.offset().and_then(|(r, c)| self.get_tile(r, c))
+        }
+
+        /// Whether a location is on the board
+        pub fn contains<L: MapLocation>(&self, location: L) -> bool{
+            self.get_tile_at(location).is_some()
+        }
+
+        /// Neighbors of a hex that are on the board, clockwise from the right
+        pub fn neighbor_coords(&self, coord: HexCoord) -> Vec<(HexDirection, HexCoord)>{
+            HexDirection::ALL.iter()
+                .map(|d| (*d, coord.neighbor(*d)))
+                .filter(|(_, n)| self.contains(*n))
+                .collect()
+        }
+
+        /// Number of steps between two hexes, given as offsets or `HexCoord`s
+        pub fn distance<A: MapLocation, B: MapLocation>(&self, a: A, b: B) -> u32{
+            a.coord().distance(b.coord())
+        }
+
+        /// Hexes on the board exactly `radius` steps from `center`, clockwise from the right
+        pub fn ring<C: MapLocation>(&self, center: C, radius: u32) -> Vec<HexCoord>{
+            let center = center.coord();
+            match radius > self.reach(center){
+                true => Vec::new(),
+                false => center.ring(radius).into_iter().filter(|h| self.contains(*h)).collect(),
+            }
+        }
+
+        /// Hexes on the board within `radius` steps of `center`, the centre first and then each ring outwards
+        pub fn spiral<C: MapLocation>(&self, center: C, radius: u32) -> Vec<HexCoord>{
+            let center = center.coord();
+            center.spiral(radius.min(self.reach(center))).into_iter().filter(|h| self.contains(*h)).collect()
+        }
+
+        /// No hex on the board is further than this from `center`, so larger rings are never built
+        fn reach(&self, center: HexCoord) -> u32{
+            let span = (self.board_width + self.board_height).min(u32::MAX as usize) as u32;
+            center.distance(HexCoord::from_offset(0, 0)).saturating_add(span)
+        }
+
+        /// Hexes on the board within `radius` steps of `center`, ordered by row then column
+        pub fn within_range<C: MapLocation>(&self, center: C, radius: u32) -> Vec<HexCoord>{
+            let mut hexes = self.spiral(center, radius);
+            hexes.sort_by_key(|h| (h.r(), h.q()));
+            hexes
+        }
+
+        /// Every hex on the board as a `HexCoord`, ordered by row then column
+        pub fn coords(&self) -> Vec<HexCoord>{
+            (0..self.board_height)
+                .flat_map(|r| (0..self.board_width).map(move |c| HexCoord::from_offset(r, c)))
+                .collect()
+        }
+
+        pub fn get_board_width(&self) -> usize{
+            self.board_width
+        }
+
+        pub fn get_board_height(&self) -> usize{
+            self.board_height
+        }
+
+        /// The seed the map was generated from
+        pub fn get_seed(&self) -> u64{
+            self.seed
+        }
+
+        /// The campaign tiles the map was generated from
+        pub fn get_campaign_tiles(&self) -> &CampaignGenerationTiles{
+            &self.base_tiles
+        }
+
+        /// How the board was split between the sides
+        pub fn get_layout(&self) -> &DeploymentLayout{
+            &self.layout
+        }
+
+        /// The river courses running across the board
+        pub fn get_rivers(&self) -> &HexNetwork{
+            &self.rivers
+        }
+
+        /// The road network running across the board
+        pub fn get_roads(&self) -> &HexNetwork{
+            &self.roads
+        }
+
+        /// Locations of every tile of the given type, ordered by row then column
+        pub fn locations_of(&self, t_type: &BattleMapTileType) -> Vec<(usize, usize)>{
+            self.tiles.iter()
+                .enumerate()
+                .filter(|(_, t)| t.get_type() == t_type)
+                .map(|(i, _)| (i / self.board_width, i % self.board_width))
+                .collect()
+        }
+
+        /// Location of the hex next to (row, column) in the given direction, None if it is off the board
+        pub fn neighbor_location(&self, row: usize, column: usize, direction: HexDirection) -> Option<(usize, usize)>{
+            let even = row % 2 == 0;
+            let (r, c): (isize, isize) = match direction{
+                HexDirection::Right => (0, 1),
+                HexDirection::Left => (0, -1),
+                HexDirection::UpperLeft => (-1, if even { -1 } else { 0 }),
+                HexDirection::UpperRight => (-1, if even { 0 } else { 1 }),
+                HexDirection::LowerRight => (1, if even { 0 } else { 1 }),
+                HexDirection::LowerLeft => (1, if even { -1 } else { 0 }),
+            };
+            let row = row.checked_add_signed(r)?;
+            let column = column.checked_add_signed(c)?;
+            self.get_tile(row, column).map(|_| (row, column))
+        }
+
+        /// Locations of all on-board neighbors of (row, column), clockwise from the right
+        pub fn neighbor_locations(&self, row: usize, column: usize) -> Vec<(HexDirection, (usize, usize))>{
+            HexDirection::ALL.iter()
+                .filter_map(|d| self.neighbor_location(row, column, *d).map(|l| (*d, l)))
+                .collect()
+        }
+
+        /// Direction from one hex to an adjacent one, None if they are not neighbors
+        pub fn direction_to(&self, from: (usize, usize), to: (usize, usize)) -> Option<HexDirection>{
+            self.neighbor_locations(from.0, from.1).into_iter()
+                .find(|(_, l)| *l == to)
+                .map(|(d, _)| d)
+        }
+
+        /// Edge of the board along which `owner` holds the most hexes under the map's layout,
+        /// None if it holds none of them
+        pub fn edge_of_owner(&self, owner: &TileOwner) -> Option<BoardEdge>{
+            BoardEdge::ALL.iter().copied()
+                .map(|e| (e, self.edge_locations(e).iter().filter(|l| self.tiles[l.0 * self.board_width + l.1].get_owner() == owner).count()))
+                .filter(|(_, held)| *held > 0)
+                .max_by_key(|(_, held)| *held)
+                .map(|(e, _)| e)
+        }
+
+        /// Locations along an edge of the board, corners included
+        pub fn edge_locations(&self, edge: BoardEdge) -> Vec<(usize, usize)>{
+            match edge{
+                BoardEdge::Top => (0..self.board_width).map(|c| (0, c)).collect(),
+                BoardEdge::Bottom => (0..self.board_width).map(|c| (self.board_height - 1, c)).collect(),
+                BoardEdge::Left => (0..self.board_height).map(|r| (r, 0)).collect(),
+                BoardEdge::Right => (0..self.board_height).map(|r| (r, self.board_width - 1)).collect(),
+            }
+        }
+
+        /// Set a tile, panics if it is not on the board, see `try_set_tile`
         pub fn set_tile(&mut self, row: usize, column: usize, tile: MapTile){
-            self.get_tile(row, column).unwrap_or_else(|| panic!("Tile must exist to set, ({},{})", row, column));
+            self.try_set_tile(row, column, tile).unwrap_or_else(|e| panic!("Tile must exist to set: {}", e));
+        }
+
+        /// Set a tile, failing if it is not on the board
+        pub fn try_set_tile(&mut self, row: usize, column: usize, tile: MapTile) -> Result<(), MapGenError>{
+            self.get_tile(row, column).ok_or_else(|| self.out_of_bounds(row, column))?;
             self.tiles[row * self.board_width + column] = tile;
+            Ok(())
+        }
+
+        fn out_of_bounds(&self, row: usize, column: usize) -> MapGenError{
+            MapGenError::OutOfBounds { row, column, width: self.board_width, height: self.board_height }
+        }
+
+        /// Change the type of a tile, keeping its owner
+        /// Panics if the tile is not on the board, see `try_set_tile_type`
+        pub fn set_tile_type(&mut self, row: usize, column: usize, t_type: BattleMapTileType){
+            self.try_set_tile_type(row, column, t_type).unwrap_or_else(|e| panic!("Tile must exist to set: {}", e));
+        }
+
+        /// Change the type of a tile, keeping its owner, failing if it is not on the board
+        pub fn try_set_tile_type(&mut self, row: usize, column: usize, t_type: BattleMapTileType) -> Result<(), MapGenError>{
+            self.get_tile_mut(row, column).map(|t| t.set_type(t_type))
+        }
+
+        /// Set or clear the river crossing on a tile, panics if it is not on the board, see `try_set_tile_crossing`
+        pub fn set_tile_crossing(&mut self, row: usize, column: usize, crossing: Option<RiverCrossing>){
+            self.try_set_tile_crossing(row, column, crossing).unwrap_or_else(|e| panic!("Tile must exist to set: {}", e));
+        }
+
+        /// Set or clear the river crossing on a tile, failing if it is not on the board
+        pub fn try_set_tile_crossing(&mut self, row: usize, column: usize, crossing: Option<RiverCrossing>) -> Result<(), MapGenError>{
+            self.get_tile_mut(row, column).map(|t| t.set_crossing(crossing))
+        }
+
+        /// Set or clear the fortification on a tile, panics if it is not on the board, see `try_set_tile_add_on`
+        pub fn set_tile_add_on(&mut self, row: usize, column: usize, add_on: Option<MapAddOn>){
+            self.try_set_tile_add_on(row, column, add_on).unwrap_or_else(|e| panic!("Tile must exist to set: {}", e));
+        }
+
+        /// Set or clear the fortification on a tile, failing if it is not on the board
+        pub fn try_set_tile_add_on(&mut self, row: usize, column: usize, add_on: Option<MapAddOn>) -> Result<(), MapGenError>{
+            self.get_tile_mut(row, column).map(|t| t.set_add_on(add_on))
+        }
+
+        /// Set the ground elevation of a tile, panics if it is not on the board, see `try_set_tile_elevation`
+        pub fn set_tile_elevation(&mut self, row: usize, column: usize, elevation: u32){
+            self.try_set_tile_elevation(row, column, elevation).unwrap_or_else(|e| panic!("Tile must exist to set: {}", e));
+        }
+
+        /// Set the ground elevation of a tile, failing if it is not on the board
+        pub fn try_set_tile_elevation(&mut self, row: usize, column: usize, elevation: u32) -> Result<(), MapGenError>{
+            self.get_tile_mut(row, column).map(|t| t.set_elevation(elevation))
+        }
+
+        fn get_tile_mut(&mut self, row: usize, column: usize) -> Result<&mut MapTile, MapGenError>{
+            self.get_tile(row, column).ok_or_else(|| self.out_of_bounds(row, column))?;
+            Ok(&mut self.tiles[row * self.board_width + column])
         }
 
 
@@ -610,102 +1661,3146 @@ pub mod battle_map{
 
 }
 
-#[cfg(test)]
-mod tests{
-    use crate::{battle_map::{Map, MapGenerator}, map_tiles::{BattleMapTileType, MapTile, TileOwner}};
 
-    #[test]
-    fn map_print(){
-        for i in 4..=10{
-            for j in 2..=10{
-                let m = MapGenerator::default().create_map(i,j);
-                m.print_board_tiles();
+pub mod coords{
+
+    use crate::battle_map::HexDirection;
+    use std::ops::{Add, Sub};
+
+    /// A hex position in axial coordinates (q, r), with the cube coordinate s = -q - r implied
+    /// Rows match the board's rows, and q grows to the right along a row
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord, Hash, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct HexCoord{
+        q: i32,
+        r: i32,
+    }
+
+    impl HexCoord{
+        /// Create from axial coordinates
+        pub fn new(q: i32, r: i32) -> Self{
+            HexCoord { q, r }
+        }
+
+        /// Create from cube coordinates, None if they do not sum to zero
+        pub fn from_cube(q: i32, r: i32, s: i32) -> Option<Self>{
+            match q + r + s == 0{
+                true => Some(HexCoord { q, r }),
+                false => None,
+            }
+        }
+
+        /// Convert from the board's odd-row offset (row, column)
+        pub fn from_offset(row: usize, column: usize) -> Self{
+            let (row, column) = (row as i32, column as i32);
+            HexCoord { q: column - (row - (row & 1)) / 2, r: row }
+        }
+
+        /// Convert to the board's odd-row offset (row, column), None if either would be negative
+        pub fn to_offset(&self) -> Option<(usize, usize)>{
+            let column = self.q + (self.r - (self.r & 1)) / 2;
+            match self.r >= 0 && column >= 0{
+                true => Some((self.r as usize, column as usize)),
+                false => None,
+            }
+        }
+
+        pub fn q(&self) -> i32{
+            self.q
+        }
+
+        pub fn r(&self) -> i32{
+            self.r
+        }
+
+        /// The third cube coordinate
+        pub fn s(&self) -> i32{
+            -self.q - self.r
+        }
+
+        /// Axial coordinates (q, r)
+        pub fn axial(&self) -> (i32, i32){
+            (self.q, self.r)
+        }
+
+        /// Cube coordinates (q, r, s)
+        pub fn cube(&self) -> (i32, i32, i32){
+            (self.q, self.r, self.s())
+        }
+
+        /// Offset of one step in a direction
+        pub fn direction(direction: HexDirection) -> HexCoord{
+            match direction{
+                HexDirection::Right => HexCoord::new(1, 0),
+                HexDirection::LowerRight => HexCoord::new(0, 1),
+                HexDirection::LowerLeft => HexCoord::new(-1, 1),
+                HexDirection::Left => HexCoord::new(-1, 0),
+                HexDirection::UpperLeft => HexCoord::new(0, -1),
+                HexDirection::UpperRight => HexCoord::new(1, -1),
+            }
+        }
+
+        /// The adjacent hex in a direction, which may be off the board
+        pub fn neighbor(&self, direction: HexDirection) -> HexCoord{
+            *self + HexCoord::direction(direction)
+        }
+
+        /// Number of steps between two hexes
+        pub fn distance(&self, other: HexCoord) -> u32{
+            let d = *self - other;
+            ((d.q.abs() + d.r.abs() + d.s().abs()) / 2) as u32
+        }
+
+        fn scale(&self, factor: i32) -> HexCoord{
+            HexCoord::new(self.q * factor, self.r * factor)
+        }
+
+        /// Hexes exactly `radius` steps away, clockwise from the one straight to the right
+        /// Radii beyond `i32::MAX` are treated as `i32::MAX`
+        pub fn ring(&self, radius: u32) -> Vec<HexCoord>{
+            if radius == 0{
+                return vec![*self];
+            }
+            let radius = radius.min(i32::MAX as u32);
+            let mut hex = *self + HexCoord::direction(HexDirection::Right).scale(radius as i32);
+            let mut ring = Vec::with_capacity(6 * radius as usize);
+            for i in 0..6{
+                let step = HexDirection::ALL[(i + 2) % 6];
+                for _ in 0..radius{
+                    ring.push(hex);
+                    hex = hex.neighbor(step);
+                }
+            }
+            ring
+        }
+
+        /// Hexes within `radius` steps, the centre first and then each ring outwards,
+        /// 3r^2 + 3r + 1 hexes in all
+        pub fn spiral(&self, radius: u32) -> Vec<HexCoord>{
+            (0..=radius).flat_map(|r| self.ring(r)).collect()
+        }
+
+        /// Hexes on the straight line to `other`, both ends included
+        /// Lines running exactly between two hexes always take the same side, so a line
+        /// and its reverse cover the same hexes
+        pub fn line_to(&self, other: HexCoord) -> Vec<HexCoord>{
+            let steps = self.distance(other);
+            if steps == 0{
+                return vec![*self];
+            }
+            let (a, b) = (self.nudged(), other.nudged());
+            (0..=steps)
+                .map(|i| {
+                    let t = i as f64 / steps as f64;
+                    HexCoord::round(a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t, a.2 + (b.2 - a.2) * t)
+                })
+                .collect()
+        }
+
+        /// Cube coordinates shifted slightly off hex edges
+        fn nudged(&self) -> (f64, f64, f64){
+            (self.q as f64 + 1e-6, self.r as f64 + 2e-6, self.s() as f64 - 3e-6)
+        }
+
+        /// Nearest hex to fractional cube coordinates
+        fn round(q: f64, r: f64, s: f64) -> HexCoord{
+            let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+            let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+            if dq > dr && dq > ds{
+                rq = -rr - rs;
+            }
+            else if dr > ds{
+                rr = -rq - rs;
             }
+            HexCoord::new(rq as i32, rr as i32)
         }
     }
 
-    #[test]
-    fn get_neighbors(){
-        // Arrange
-        let own = TileOwner::Attacker;
-        let mut m = MapGenerator::default().create_map(4, 3);
-        m.set_tile(0, 0, MapTile::new(BattleMapTileType::Plains));
-        m.set_tile(0, 1, MapTile::new(BattleMapTileType::Forest));
-        m.set_tile(0, 2, MapTile::new(BattleMapTileType::Hill));
-        m.set_tile(1, 0, MapTile::new(BattleMapTileType::Mountain));
-        m.set_tile(1, 1, MapTile::new(BattleMapTileType::Outpost));
-        m.set_tile(2, 0, MapTile::new(BattleMapTileType::Road));
-        m.set_tile(2, 1, MapTile::new(BattleMapTileType::Swamp));
-        m.set_tile(2, 2, MapTile::new(BattleMapTileType::Town));
-        
-        // Act
-        let neighbors_0_0 = m.get_neighbors(0, 0);
-        let neighbors_1_1 = m.get_neighbors(1, 1);
-        let neighbors_2_1 = m.get_neighbors(2, 1);
+    impl Add for HexCoord{
+        type Output = HexCoord;
 
-        // Assert
-        assert_eq!(neighbors_0_0.get_tile_location(), (0,0));
-        assert_eq!(neighbors_0_0.tile_type().get_type(), &BattleMapTileType::Plains);
-        assert_eq!(neighbors_0_0.get_left(), None);
-        assert_eq!(neighbors_0_0.get_upper_left(), None);
-        assert_eq!(neighbors_0_0.get_upper_right(), None);
-        assert_eq!(neighbors_0_0.get_right().unwrap().get_type(), &BattleMapTileType::Forest);
-        assert_eq!(neighbors_0_0.get_lower_right().unwrap().get_type(), &BattleMapTileType::Mountain);
-        assert_eq!(neighbors_0_0.get_lower_left(), None);
-        
-        assert_eq!(neighbors_1_1.get_tile_location(), (1,1));
-        assert_eq!(neighbors_1_1.tile_type().get_type(), &BattleMapTileType::Outpost);
-        assert_eq!(neighbors_1_1.get_left().unwrap().get_type(), &BattleMapTileType::Mountain);
-        assert_eq!(neighbors_1_1.get_upper_left().unwrap().get_type(), &BattleMapTileType::Forest);
-        assert_eq!(neighbors_1_1.get_upper_right().unwrap().get_type(), &BattleMapTileType::Hill);
-        assert_eq!(neighbors_1_1.get_right().unwrap().get_type(), &BattleMapTileType::Default);
-        assert_eq!(neighbors_1_1.get_lower_right().unwrap().get_type(), &BattleMapTileType::Town);
-        assert_eq!(neighbors_1_1.get_lower_left().unwrap().get_type(), &BattleMapTileType::Swamp);
-        
-        assert_eq!(neighbors_2_1.get_tile_location(), (2,1));
-        assert_eq!(neighbors_2_1.tile_type().get_type(), &BattleMapTileType::Swamp);
-        assert_eq!(neighbors_2_1.get_left().unwrap().get_type(), &BattleMapTileType::Road);
-        assert_eq!(neighbors_2_1.get_upper_left().unwrap().get_type(), &BattleMapTileType::Mountain);
-        assert_eq!(neighbors_2_1.get_upper_right().unwrap().get_type(), &BattleMapTileType::Outpost);
-        assert_eq!(neighbors_2_1.get_right().unwrap().get_type(), &BattleMapTileType::Town);
-        assert_eq!(neighbors_2_1.get_lower_right(), None);
-        assert_eq!(neighbors_2_1.get_lower_left(), None);
+        fn add(self, other: HexCoord) -> HexCoord{
+            HexCoord::new(self.q + other.q, self.r + other.r)
+        }
     }
 
+    impl Sub for HexCoord{
+        type Output = HexCoord;
 
-    #[test]
-    fn tile_owners(){
+        fn sub(self, other: HexCoord) -> HexCoord{
+            HexCoord::new(self.q - other.q, self.r - other.r)
+        }
+    }
 
-        // even width, even height
-        let mut m = MapGenerator::default().create_map(4, 2);
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 3).unwrap().get_owner());
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(1, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(1, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(1, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 3).unwrap().get_owner());
+    impl From<(usize, usize)> for HexCoord{
+        fn from(offset: (usize, usize)) -> Self{
+            HexCoord::from_offset(offset.0, offset.1)
+        }
+    }
 
-        // even width, odd height
-        let mut m = MapGenerator::default().create_map(4, 3);
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 3).unwrap().get_owner());
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(1, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 3).unwrap().get_owner());
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(2, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(2, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(2, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(2, 3).unwrap().get_owner());
-        
-        // odd width, even height
-        let mut m = MapGenerator::default().create_map(5, 2);
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
+    /// A position that can be looked up on a `Map`, either an offset (row, column) or a `HexCoord`
+    pub trait MapLocation{
+        /// The offset (row, column), None if the position cannot be on any board
+        fn offset(&self) -> Option<(usize, usize)>;
+
+        /// The position as a `HexCoord`
+        fn coord(&self) -> HexCoord;
+    }
+
+    impl MapLocation for (usize, usize){
+        fn offset(&self) -> Option<(usize, usize)>{
+            Some(*self)
+        }
+
+        fn coord(&self) -> HexCoord{
+            HexCoord::from(*self)
+        }
+    }
+
+    impl MapLocation for HexCoord{
+        fn offset(&self) -> Option<(usize, usize)>{
+            self.to_offset()
+        }
+
+        fn coord(&self) -> HexCoord{
+            *self
+        }
+    }
+
+}
+
+pub mod deployment{
+    use crate::battle_map::{BoardEdge, HexDirection, Map};
+    use crate::errors::MapGenError;
+    use crate::map_tiles::{normalize_name, TileOwner};
+    use std::fmt;
+    use std::str::FromStr;
+
+    /// How the board is split between the attacker, defender and flanks
+    /// The built-in layouts are turned so the attacker holds the edge its attack comes over,
+    /// custom masks are used as given
+    #[derive(Clone, PartialEq, Eq, Debug, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum DeploymentLayout{
+        /// Flanks a quarter of the way in from each side and at least one tile wide, the attacker and defender half the depth each,
+        /// with the middle row split between them when the depth is odd
+        #[default]
+        Classic,
+        /// The front line slants across the board, letting the attacker push deep past the defender's right
+        FlankingAssault,
+        /// The defender holds the middle of the board, ringed by the attacker ahead and behind and the flanks to each side
+        Encirclement,
+        /// Both sides arrive at once, with narrow flanks and the middle third of the board split between them
+        MeetingEngagement,
+        /// A river runs between the flanks, with the attacker on the near bank and the defender on the far one
+        RiverCrossing,
+        /// An owner for every tile of a board this size, row-major
+        Custom { width: usize, height: usize, owners: Vec<TileOwner> },
+    }
+
+    impl DeploymentLayout{
+        pub const BUILT_IN: [DeploymentLayout; 5] = [
+            DeploymentLayout::Classic, DeploymentLayout::FlankingAssault, DeploymentLayout::Encirclement,
+            DeploymentLayout::MeetingEngagement, DeploymentLayout::RiverCrossing
+        ];
+
+        /// A custom layout drawn one board row per line with the letters printed by `print_board_owners`,
+        /// A, D, L, R or S for the split row, whitespace between them is ignored
+        pub fn from_mask(text: &str) -> Result<Self, MapGenError>{
+            let mut rows: Vec<Vec<TileOwner>> = vec![];
+            for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()){
+                let row = line.chars()
+                    .filter(|c| !c.is_whitespace())
+                    .map(|c| match c.to_ascii_uppercase(){
+                        'A' => Ok(TileOwner::Attacker),
+                        'D' => Ok(TileOwner::Defender),
+                        'L' => Ok(TileOwner::LeftFlank),
+                        'R' => Ok(TileOwner::RightFlank),
+                        'S' => Ok(TileOwner::SplitAttDef),
+                        _ => Err(MapGenError::Parse(format!("Mask line {}: unknown owner {}", i + 1, c))),
+                    })
+                    .collect::<Result<Vec<TileOwner>, _>>()?;
+                if let Some(first) = rows.first().filter(|f| f.len() != row.len()){
+                    return Err(MapGenError::Parse(format!("Mask line {}: row has {} tiles, expected {}", i + 1, row.len(), first.len())));
+                }
+                rows.push(row);
+            }
+            Ok(DeploymentLayout::Custom {
+                width: rows.first().map_or(0, |r| r.len()),
+                height: rows.len(),
+                owners: rows.into_iter().flatten().collect(),
+            })
+        }
+
+        /// Owner of every tile of a board, row-major, with the attack coming from `attack`
+        /// Fails when the board is under 3 tiles across the attack, a custom mask is a different size
+        /// or has the wrong number of owners, or the attacker, defender or either flank gets no tiles
+        pub fn owners(&self, width: usize, height: usize, attack: HexDirection) -> Result<Vec<TileOwner>, MapGenError>{
+            let owners = match self{
+                DeploymentLayout::Custom { width: w, height: h, owners } => match ((*w, *h) == (width, height), owners.len() == width * height){
+                    (true, true) => owners.clone(),
+                    (true, false) => return Err(MapGenError::TileCountMismatch { expected: width * height, found: owners.len() }),
+                    (false, _) => return Err(MapGenError::MaskSizeMismatch { board: (width, height), mask: (*w, *h) }),
+                },
+                layout => {
+                    let attack_edge = BoardEdge::from_direction(attack);
+                    // the flanks and the attacker and defender between them need a row each across the attack
+                    let breadth = match attack_edge{
+                        BoardEdge::Top | BoardEdge::Bottom => width,
+                        BoardEdge::Left | BoardEdge::Right => height,
+                    };
+                    if breadth < 3{
+                        return Err(MapGenError::BoardTooSmall { width, height });
+                    }
+                    (0..height)
+                        .flat_map(|r| (0..width).map(move |c| (r, c)))
+                        .map(|l| {
+                            let (depth, across, length, breadth) = attack_frame(width, height, attack_edge, l);
+                            layout.owner(depth, across, length, breadth)
+                        })
+                        .collect()
+                },
+            };
+            check_deployed(&owners)?;
+            Ok(owners)
+        }
+
+        /// Owner of every tile of a map, row-major, once its rivers are in place
+        /// The river crossing layout gives the attacker everything between the flanks on its side of the river
+        /// and the defender the rest, so neither can reach the other without crossing, river hexes keep their owner
+        /// Fails like `owners` when an owner is left without tiles
+        pub fn finalize(&self, map: &Map) -> Result<Vec<TileOwner>, MapGenError>{
+            let (width, height) = (map.get_board_width(), map.get_board_height());
+            let locations: Vec<(usize, usize)> = (0..height).flat_map(|r| (0..width).map(move |c| (r, c))).collect();
+            let mut owners: Vec<TileOwner> = locations.iter()
+                .map(|l| map.get_tile(l.0, l.1).expect("Locations are on the board").get_owner().clone())
+                .collect();
+            if *self == DeploymentLayout::RiverCrossing{
+                let rivers = map.get_rivers();
+                let attack = map.get_campaign_tiles().get_attack_direction();
+                let mut near_bank = vec![false; owners.len()];
+                let mut queue: Vec<(usize, usize)> = map.edge_locations(BoardEdge::from_direction(attack)).into_iter()
+                    .filter(|l| !rivers.contains(*l))
+                    .collect();
+                for l in queue.iter(){
+                    near_bank[l.0 * width + l.1] = true;
+                }
+                while let Some(l) = queue.pop(){
+                    for (_, n) in map.neighbor_locations(l.0, l.1){
+                        if !near_bank[n.0 * width + n.1] && !rivers.contains(n){
+                            near_bank[n.0 * width + n.1] = true;
+                            queue.push(n);
+                        }
+                    }
+                }
+                for (i, owner) in owners.iter_mut().enumerate(){
+                    if rivers.contains(locations[i]) || matches!(owner, TileOwner::LeftFlank | TileOwner::RightFlank){
+                        continue;
+                    }
+                    *owner = match near_bank[i]{
+                        true => TileOwner::Attacker,
+                        false => TileOwner::Defender,
+                    };
+                }
+            }
+            check_deployed(&owners)?;
+            Ok(owners)
+        }
+
+        /// Edges for the rivers to run between, the river crossing layout sends its main course between the flanks
+        /// ahead of any river edges from the campaign tiles
+        pub fn river_edges(&self, edges: Vec<BoardEdge>, attack: HexDirection) -> Vec<BoardEdge>{
+            match self{
+                DeploymentLayout::RiverCrossing => {
+                    let banks: Vec<BoardEdge> = [TileOwner::LeftFlank, TileOwner::RightFlank].iter()
+                        .filter_map(|o| BoardEdge::of_owner(o, attack))
+                        .collect();
+                    let rest = edges.into_iter().filter(|e| !banks.contains(e));
+                    banks.iter().copied().chain(rest).collect()
+                },
+                _ => edges,
+            }
+        }
+
+        /// Cost of the main river course running through a location, the river crossing layout
+        /// keeps it close to the middle of the board's depth so both banks have room
+        pub fn river_cost(&self, width: usize, height: usize, attack: HexDirection, location: (usize, usize)) -> u32{
+            match self{
+                DeploymentLayout::RiverCrossing => 1 + 4 * rows_from_middle(width, height, attack, location) as u32,
+                _ => 1,
+            }
+        }
+
+        /// Owner of a tile `depth` rows from the attacker's edge and `across` from the defender's left,
+        /// on a board `length` deep and `breadth` across
+        fn owner(&self, depth: usize, across: usize, length: usize, breadth: usize) -> TileOwner{
+            let flank_width = match self{
+                DeploymentLayout::MeetingEngagement | DeploymentLayout::RiverCrossing => breadth / 6,
+                _ => breadth / 4,
+            }.max(1);
+            let flank = match across{
+                a if a < flank_width => Some(TileOwner::LeftFlank),
+                a if a >= breadth - flank_width => Some(TileOwner::RightFlank),
+                _ => None,
+            };
+            // attacker above the front, defender from the rear line down and the split row between
+            let (front, rear) = match self{
+                DeploymentLayout::FlankingAssault => {
+                    let front = length * (breadth + 2 * across) / (4 * breadth);
+                    (front, front)
+                },
+                DeploymentLayout::Encirclement => {
+                    let (ring, side) = (length / 4, breadth / 4);
+                    let inside = (ring..length - ring).contains(&depth) && (side..breadth - side).contains(&across);
+                    return match (inside, flank){
+                        (true, _) => TileOwner::Defender,
+                        (false, Some(f)) => f,
+                        (false, None) => TileOwner::Attacker,
+                    };
+                },
+                DeploymentLayout::MeetingEngagement => ((length + 1) / 3, length - (length + 1) / 3),
+                _ => (length / 2, length - length / 2),
+            };
+            match (flank, depth){
+                (Some(f), _) => f,
+                (None, d) if d < front => TileOwner::Attacker,
+                (None, d) if d >= rear => TileOwner::Defender,
+                _ => TileOwner::SplitAttDef,
+            }
+        }
+    }
+
+    /// A location's distance from the attacker's edge and from the defender's left, with the board's depth and breadth
+    fn attack_frame(width: usize, height: usize, attack_edge: BoardEdge, (r, c): (usize, usize)) -> (usize, usize, usize, usize){
+        match attack_edge{
+            BoardEdge::Top => (r, c, height, width),
+            BoardEdge::Bottom => (height - 1 - r, width - 1 - c, height, width),
+            BoardEdge::Right => (width - 1 - c, r, width, height),
+            BoardEdge::Left => (c, height - 1 - r, width, height),
+        }
+    }
+
+    /// Every side must get some of the board
+    fn check_deployed(owners: &[TileOwner]) -> Result<(), MapGenError>{
+        for owner in [TileOwner::Attacker, TileOwner::Defender, TileOwner::LeftFlank, TileOwner::RightFlank]{
+            if !owners.contains(&owner){
+                return Err(MapGenError::EmptyDeployment { owner });
+            }
+        }
+        Ok(())
+    }
+
+    /// Rows between a location and the middle of the board's depth, 0 on the middle row or either of the two middle rows
+    fn rows_from_middle(width: usize, height: usize, attack: HexDirection, location: (usize, usize)) -> usize{
+        let (depth, _, length, _) = attack_frame(width, height, BoardEdge::from_direction(attack), location);
+        (2 * depth + 1).abs_diff(length) / 2
+    }
+
+    impl fmt::Display for DeploymentLayout{
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            let s = match self{
+                DeploymentLayout::Classic => "Classic",
+                DeploymentLayout::FlankingAssault => "FlankingAssault",
+                DeploymentLayout::Encirclement => "Encirclement",
+                DeploymentLayout::MeetingEngagement => "MeetingEngagement",
+                DeploymentLayout::RiverCrossing => "RiverCrossing",
+                DeploymentLayout::Custom { .. } => "Custom",
+            };
+            write!(f, "{}", s)
+        }
+    }
+
+    impl FromStr for DeploymentLayout{
+        type Err = MapGenError;
+
+        /// Parse a built-in layout, ignoring case and any '_', '-' or ' ', e.g. "river-crossing" or "RiverCrossing"
+        fn from_str(s: &str) -> Result<Self, Self::Err>{
+            match normalize_name(s).as_str(){
+                "classic" => Ok(DeploymentLayout::Classic),
+                "flankingassault" | "flanking" => Ok(DeploymentLayout::FlankingAssault),
+                "encirclement" => Ok(DeploymentLayout::Encirclement),
+                "meetingengagement" | "meeting" => Ok(DeploymentLayout::MeetingEngagement),
+                "rivercrossing" => Ok(DeploymentLayout::RiverCrossing),
+                _ => Err(MapGenError::Parse(format!("Unknown deployment layout: {}", s))),
+            }
+        }
+    }
+}
+
+pub mod elevation{
+
+    use crate::map_tiles::CampaignGenerationTiles;
+    use rand::Rng;
+
+    /// Highest elevation level on the roughest maps
+    pub const MAX_ELEVATION: u32 = 20;
+
+    /// Elevation levels on even the flattest maps
+    pub const MIN_RELIEF: u32 = 5;
+
+    /// How rough the ground is from 0 to 1, from the campaign's mountain and hill density
+    pub fn roughness(tiles: &CampaignGenerationTiles) -> f64{
+        (tiles.mountain_density() + 0.5 * tiles.hill_density()).clamp(0.0, 1.0)
+    }
+
+    /// Highest elevation level a map of the given roughness reaches
+    pub fn max_elevation(roughness: f64) -> u32{
+        MIN_RELIEF + (roughness.clamp(0.0, 1.0) * (MAX_ELEVATION - MIN_RELIEF) as f64).round() as u32
+    }
+
+    /// Elevation level of every hex, row-major, from two octaves of value noise
+    /// Rougher ground has higher peaks and smaller, steeper features
+    pub fn generate_heightmap<R: Rng + ?Sized>(width: usize, height: usize, roughness: f64, gen: &mut R) -> Vec<u32>{
+        let roughness = roughness.clamp(0.0, 1.0);
+        let spacing = 6.0 - 3.0 * roughness;
+        let coarse = ValueNoise::new(width, height, spacing, gen);
+        let fine = ValueNoise::new(width, height, spacing / 2.0, gen);
+        let detail = 0.25 + 0.5 * roughness;
+
+        let noise: Vec<f64> = (0..height)
+            .flat_map(|r| (0..width).map(move |c| hex_center(r, c)))
+            .map(|(x, y)| coarse.sample(x, y) + detail * fine.sample(x, y))
+            .collect();
+        let low = noise.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = noise.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let top = max_elevation(roughness) as f64;
+        noise.into_iter()
+            .map(|n| match high > low{
+                true => ((n - low) / (high - low) * top).round() as u32,
+                false => 0,
+            })
+            .collect()
+    }
+
+    /// The elevation level where the number of hexes at or above it is closest to `count`,
+    /// the higher level when two are equally close, None when `count` is 0
+    pub fn threshold_for_count(heights: &[u32], count: usize) -> Option<u32>{
+        if count == 0 || heights.is_empty(){
+            return None;
+        }
+        let top = *heights.iter().max()?;
+        (0..=top).rev()
+            .map(|level| (level, heights.iter().filter(|h| **h >= level).count().abs_diff(count)))
+            .min_by_key(|(_, diff)| *diff)
+            .map(|(level, _)| level)
+    }
+
+    /// Centre of a hex in hex widths, odd rows shifted half a hex right
+    fn hex_center(row: usize, column: usize) -> (f64, f64){
+        (column as f64 + 0.5 * (row & 1) as f64, row as f64 * 0.75_f64.sqrt())
+    }
+
+    /// Random values on a square lattice, smoothly interpolated between lattice points
+    struct ValueNoise{
+        spacing: f64,
+        columns: usize,
+        values: Vec<f64>,
+    }
+
+    impl ValueNoise{
+        fn new<R: Rng + ?Sized>(width: usize, height: usize, spacing: f64, gen: &mut R) -> Self{
+            let columns = (width as f64 / spacing).ceil() as usize + 2;
+            let rows = (height as f64 / spacing).ceil() as usize + 2;
+            let values = (0..columns * rows).map(|_| gen.gen::<f64>()).collect();
+            ValueNoise { spacing, columns, values }
+        }
+
+        fn sample(&self, x: f64, y: f64) -> f64{
+            let (fx, fy) = (x / self.spacing, y / self.spacing);
+            let (ix, iy) = (fx.floor() as usize, fy.floor() as usize);
+            let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+            let (tx, ty) = (smooth(fx - ix as f64), smooth(fy - iy as f64));
+            let at = |cx: usize, cy: usize| self.values[cy * self.columns + cx];
+            let top = at(ix, iy) + (at(ix + 1, iy) - at(ix, iy)) * tx;
+            let bottom = at(ix, iy + 1) + (at(ix + 1, iy + 1) - at(ix, iy + 1)) * tx;
+            top + (bottom - top) * ty
+        }
+    }
+
+}
+
+pub mod clusters{
+
+    use crate::battle_map::{random_index, Map};
+    use crate::map_tiles::BattleMapTileType;
+    use rand::Rng;
+
+    /// Chance each added hex starts a new cluster rather than growing an existing one
+    pub const NEW_CLUSTER_CHANCE: f64 = 0.15;
+
+    /// Pick `count` of the candidate hexes as clusters grown outward from seeded starting hexes
+    /// Both the starting hexes and each hex a cluster grows into are drawn in proportion to `weight`
+    pub fn grow_clusters<R: Rng + ?Sized, F: Fn((usize, usize)) -> u32>(map: &Map, candidates: &[(usize, usize)], count: usize, weight: F, gen: &mut R) -> Vec<(usize, usize)>{
+        // each board hex's index into the candidates, if it is one
+        let mut slots = vec![None; map.get_board_width() * map.get_board_height()];
+        for (i, l) in candidates.iter().enumerate(){
+            slots[l.0 * map.get_board_width() + l.1] = Some(i);
+        }
+        let weights: Vec<u32> = candidates.iter().map(|l| weight(*l).max(1)).collect();
+        let mut free = WeightedPool::new(weights.len());
+        for (i, w) in weights.iter().enumerate(){
+            free.insert(i, *w);
+        }
+        let mut frontier = WeightedPool::new(weights.len());
+        let mut chosen = vec![];
+        while chosen.len() < count && !free.is_empty(){
+            let pool = match frontier.is_empty() || gen.gen_bool(NEW_CLUSTER_CHANCE){
+                true => &free,
+                false => &frontier,
+            };
+            let slot = pool.pick(gen);
+            free.remove(slot);
+            frontier.remove(slot);
+            let hex = candidates[slot];
+            chosen.push(hex);
+            for (_, n) in map.neighbor_locations(hex.0, hex.1){
+                if let Some(s) = slots[n.0 * map.get_board_width() + n.1]{
+                    if free.contains(s) && !frontier.contains(s){
+                        frontier.insert(s, weights[s]);
+                    }
+                }
+            }
+        }
+        chosen
+    }
+
+    /// Weighted set of slots with logarithmic insertion, removal and draws, kept as a Fenwick tree
+    struct WeightedPool{
+        weights: Vec<u32>,
+        tree: Vec<u64>,
+        total: u64,
+    }
+
+    impl WeightedPool{
+        fn new(len: usize) -> Self{
+            WeightedPool{ weights: vec![0; len], tree: vec![0; len + 1], total: 0 }
+        }
+
+        fn is_empty(&self) -> bool{
+            self.total == 0
+        }
+
+        fn contains(&self, slot: usize) -> bool{
+            self.weights[slot] > 0
+        }
+
+        /// Add a slot with a non-zero weight, or update its weight
+        fn insert(&mut self, slot: usize, weight: u32){
+            let old = std::mem::replace(&mut self.weights[slot], weight);
+            self.add(slot, weight as i64 - old as i64);
+        }
+
+        fn remove(&mut self, slot: usize){
+            self.insert(slot, 0);
+        }
+
+        fn add(&mut self, slot: usize, delta: i64){
+            self.total = self.total.wrapping_add_signed(delta);
+            let mut i = slot + 1;
+            while i < self.tree.len(){
+                self.tree[i] = self.tree[i].wrapping_add_signed(delta);
+                i += i & i.wrapping_neg();
+            }
+        }
+
+        /// A slot drawn in proportion to its weight, the pool must not be empty
+        fn pick<R: Rng + ?Sized>(&self, gen: &mut R) -> usize{
+            let mut rest = gen.gen_range(0..self.total);
+            let mut i = 0;
+            let mut step = (self.tree.len() - 1).checked_next_power_of_two().unwrap_or(0);
+            while step > 0{
+                if i + step < self.tree.len() && self.tree[i + step] <= rest{
+                    i += step;
+                    rest -= self.tree[i];
+                }
+                step /= 2;
+            }
+            i
+        }
+    }
+
+    /// How strongly a swamp is drawn to a hex, more the lower it lies below `top`
+    /// and much more beside a river
+    pub fn swamp_weight(map: &Map, location: (usize, usize), top: u32) -> u32{
+        let low = top.saturating_sub(map.get_tile(location.0, location.1).map_or(top, |t| t.get_elevation()));
+        let riverside = map.neighbor_locations(location.0, location.1).iter()
+            .any(|(_, n)| map.get_tile(n.0, n.1).is_some_and(|t| t.get_type() == &BattleMapTileType::River));
+        1 + low + match riverside{
+            true => 8,
+            false => 0,
+        }
+    }
+
+    /// An index drawn in proportion to its weight
+    pub(crate) fn weighted_index<R: Rng + ?Sized>(weights: &[u32], gen: &mut R) -> usize{
+        let total: u32 = weights.iter().sum();
+        let mut pick = random_index(gen, total as usize) as u32;
+        for (i, w) in weights.iter().enumerate(){
+            if pick < *w{
+                return i;
+            }
+            pick -= w;
+        }
+        weights.len() - 1
+    }
+
+}
+
+pub mod hex_paths{
+
+    use crate::battle_map::{random_index, BoardEdge, HexDirection, Map};
+    use rand::Rng;
+    use std::cmp::Reverse;
+    use std::collections::{BTreeMap, BinaryHeap};
+
+    /// Shape a path makes through a hex, shared by the river and road tiles
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum PathShape{
+        End, // no connections or a single one
+        Straight,
+        Turn120,
+        BranchY,
+        Branch120,
+    }
+
+    impl PathShape{
+        /// Shape of a path connecting in the given directions,
+        /// sharp turns and junctions of four or more use the closest available tile
+        pub fn from_connections(connections: &[HexDirection]) -> Self{
+            match connections{
+                [] | [_] => PathShape::End,
+                [a, b] => match a.turn_steps(*b){
+                    3 => PathShape::Straight,
+                    _ => PathShape::Turn120,
+                },
+                [a, b, c] if a.turn_steps(*b) == 2 && b.turn_steps(*c) == 2 && c.turn_steps(*a) == 2 => PathShape::BranchY,
+                _ => PathShape::Branch120,
+            }
+        }
+    }
+
+    /// Connected paths across the board, e.g. rivers or roads
+    /// Each hex stores the directions it connects in, a direction pointing off the board is an exit
+    #[derive(Clone, PartialEq, Eq, Debug, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(feature = "serde", serde(from = "Vec<NetworkHex>", into = "Vec<NetworkHex>"))]
+    pub struct HexNetwork{
+        connections: BTreeMap<(usize, usize), Vec<HexDirection>>,
+    }
+
+    /// One hex of a network as written to JSON, whose object keys cannot be locations
+    #[cfg(feature = "serde")]
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct NetworkHex{
+        location: (usize, usize),
+        connections: Vec<HexDirection>,
+    }
+
+    #[cfg(feature = "serde")]
+    impl From<Vec<NetworkHex>> for HexNetwork{
+        fn from(hexes: Vec<NetworkHex>) -> Self{
+            HexNetwork { connections: hexes.into_iter().map(|h| (h.location, h.connections)).collect() }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl From<HexNetwork> for Vec<NetworkHex>{
+        fn from(network: HexNetwork) -> Self{
+            network.connections.into_iter().map(|(location, connections)| NetworkHex { location, connections }).collect()
+        }
+    }
+
+    impl HexNetwork{
+        pub fn new() -> Self{
+            HexNetwork { connections: BTreeMap::new() }
+        }
+
+        pub fn is_empty(&self) -> bool{
+            self.connections.is_empty()
+        }
+
+        pub fn len(&self) -> usize{
+            self.connections.len()
+        }
+
+        pub fn contains(&self, location: (usize, usize)) -> bool{
+            self.connections.contains_key(&location)
+        }
+
+        /// All hexes in the network, ordered by row then column
+        pub fn locations(&self) -> Vec<(usize, usize)>{
+            self.connections.keys().copied().collect()
+        }
+
+        /// Directions the hex connects in, empty if the hex is not in the network
+        pub fn get_connections(&self, location: (usize, usize)) -> &[HexDirection]{
+            self.connections.get(&location).map(|c| c.as_slice()).unwrap_or(&[])
+        }
+
+        /// Add a hex without connecting it to anything
+        pub fn add_location(&mut self, location: (usize, usize)){
+            self.connections.entry(location).or_default();
+        }
+
+        /// Connect a hex in a direction, also connecting the neighbor back if it is on the board
+        pub fn connect(&mut self, map: &Map, location: (usize, usize), direction: HexDirection){
+            Self::push_direction(self.connections.entry(location).or_default(), direction);
+            if let Some(n) = map.neighbor_location(location.0, location.1, direction){
+                Self::push_direction(self.connections.entry(n).or_default(), direction.opposite());
+            }
+        }
+
+        /// Add a path of adjacent hexes, connecting each to the next
+        pub fn add_path(&mut self, map: &Map, path: &[(usize, usize)]){
+            for l in path{
+                self.add_location(*l);
+            }
+            for w in path.windows(2){
+                let d = map.direction_to(w[0], w[1]).expect("Path hexes must be adjacent");
+                self.connect(map, w[0], d);
+            }
+        }
+
+        /// Connect a hex off the board across the given edge,
+        /// choosing the exit that turns least from the hex's existing connections
+        pub fn add_exit(&mut self, map: &Map, location: (usize, usize), edge: BoardEdge){
+            let existing = self.get_connections(location).to_vec();
+            let exit = edge.exit_directions().iter()
+                .copied()
+                .filter(|d| map.neighbor_location(location.0, location.1, *d).is_none())
+                .max_by_key(|d| existing.iter().map(|e| e.turn_steps(*d)).min().unwrap_or(3));
+            if let Some(d) = exit{
+                self.connect(map, location, d);
+            }
+        }
+
+        /// Whether the hex connects off the board
+        pub fn is_exit(&self, map: &Map, location: (usize, usize)) -> bool{
+            self.get_connections(location).iter()
+                .any(|d| map.neighbor_location(location.0, location.1, *d).is_none())
+        }
+
+        fn push_direction(dirs: &mut Vec<HexDirection>, direction: HexDirection){
+            if !dirs.contains(&direction){
+                dirs.push(direction);
+                dirs.sort();
+            }
+        }
+    }
+
+    /// Find a cheapest path from `start` to any of `targets`, picking randomly between equally cheap steps
+    /// so repeated calls meander differently. `cost` gives the cost of entering a hex, None if it is impassable,
+    /// costs below 1 are treated as 1. Returns None if no target can be reached.
+    pub fn cheapest_path<R, F>(map: &Map, start: (usize, usize), targets: &[(usize, usize)], cost: F, gen: &mut R) -> Option<Vec<(usize, usize)>>
+    where R: Rng, F: Fn((usize, usize)) -> Option<u32>{
+        let width = map.get_board_width();
+        let index = |l: (usize, usize)| l.0 * width + l.1;
+        let step_cost = |l: (usize, usize)| cost(l).map(|c| c.max(1));
+
+        // cost from every hex to the nearest target, searching out from the targets
+        let mut dist: Vec<Option<u32>> = vec![None; width * map.get_board_height()];
+        let mut heap = BinaryHeap::new();
+        for t in targets{
+            if map.get_tile(t.0, t.1).is_some() && step_cost(*t).is_some(){
+                dist[index(*t)] = Some(0);
+                heap.push(Reverse((0, *t)));
+            }
+        }
+        while let Some(Reverse((d, l))) = heap.pop(){
+            if dist[index(l)].is_some_and(|x| x < d){
+                continue;
+            }
+            // stepping from a neighbor into l costs l's entry cost
+            let Some(c) = step_cost(l) else { continue };
+            for (_, n) in map.neighbor_locations(l.0, l.1){
+                let nd = d + c;
+                if dist[index(n)].is_none_or(|x| nd < x){
+                    dist[index(n)] = Some(nd);
+                    heap.push(Reverse((nd, n)));
+                }
+            }
+        }
+
+        // walk downhill from the start
+        let mut current = start;
+        let mut path = vec![start];
+        let mut remaining = dist[index(start)]?;
+        while remaining > 0{
+            let options: Vec<(usize, usize)> = map.neighbor_locations(current.0, current.1).into_iter()
+                .map(|(_, n)| n)
+                .filter(|n| match (dist[index(*n)], step_cost(*n)){
+                    (Some(d), Some(c)) => d + c == remaining,
+                    _ => false,
+                })
+                .collect();
+            let next = options[random_index(gen, options.len())];
+            remaining = dist[index(next)].expect("Step must have a distance");
+            path.push(next);
+            current = next;
+        }
+        Some(path)
+    }
+
+    /// A random hex along an edge, avoiding the corners when the edge is long enough
+    pub fn random_edge_location<R: Rng>(map: &Map, edge: BoardEdge, gen: &mut R) -> (usize, usize){
+        let locations = inner_edge_locations(map, edge);
+        locations[random_index(gen, locations.len())]
+    }
+
+    /// Hexes a path may finish on along an edge, never the hex it started from
+    pub fn edge_targets(map: &Map, edge: BoardEdge, start: (usize, usize)) -> Vec<(usize, usize)>{
+        let targets: Vec<(usize, usize)> = inner_edge_locations(map, edge).into_iter().filter(|l| *l != start).collect();
+        match targets.is_empty(){
+            true => map.edge_locations(edge).into_iter().filter(|l| *l != start).collect(),
+            false => targets,
+        }
+    }
+
+    /// Hexes along an edge without the corners, unless the edge is too short to have any
+    fn inner_edge_locations(map: &Map, edge: BoardEdge) -> Vec<(usize, usize)>{
+        let locations = map.edge_locations(edge);
+        match locations.len() > 2{
+            true => locations[1..locations.len() - 1].to_vec(),
+            false => locations,
+        }
+    }
+
+}
+
+
+pub mod rivers{
+
+    use crate::battle_map::{random_index, BoardEdge, HexDirection, Map};
+    use crate::hex_paths::{cheapest_path, edge_targets, random_edge_location, HexNetwork, PathShape};
+    use crate::map_tiles::{BattleMapTileType, CampaignGenerationTiles, CampaignMapTileAddOn, RiverCrossing};
+    use rand::Rng;
+
+    /// River hexes per ford placed along a river
+    const RIVER_TILES_PER_FORD: usize = 8;
+
+    /// Shape of a river hex, from the tiles available in the base game
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum RiverTileType{
+        Straight, // has 3 rotations
+        Turn120, // has 6 rotations
+        BranchY, // has 2 rotations
+        Branch120, // has 6 rotations
+        Lake, // has 6 rotations
+    }
+
+    impl RiverTileType{
+        /// Shape of a river hex connecting in the given directions, a river ending in the hex is a lake
+        pub fn from_connections(connections: &[HexDirection]) -> Self{
+            match PathShape::from_connections(connections){
+                PathShape::End => RiverTileType::Lake,
+                PathShape::Straight => RiverTileType::Straight,
+                PathShape::Turn120 => RiverTileType::Turn120,
+                PathShape::BranchY => RiverTileType::BranchY,
+                PathShape::Branch120 => RiverTileType::Branch120,
+            }
+        }
+    }
+
+    /// Shape of the river at a location on the map, None if there is no river there
+    pub fn river_tile_type(map: &Map, location: (usize, usize)) -> Option<RiverTileType>{
+        match map.get_rivers().contains(location){
+            true => Some(RiverTileType::from_connections(map.get_rivers().get_connections(location))),
+            false => None,
+        }
+    }
+
+    /// Edges of the battle map that a river must cross, from the campaign tiles carrying a river
+    pub fn river_edges(tiles: &CampaignGenerationTiles) -> Vec<BoardEdge>{
+        BoardEdge::ALL.iter()
+            .copied()
+            .filter(|e| e.campaign_tile(tiles).has_river())
+            .collect()
+    }
+
+    /// Generate connected river courses crossing the given edges
+    /// The first two edges are joined by the main course, a single edge flows to the opposite edge,
+    /// and every further edge branches into the existing river
+    pub fn generate_rivers<R: Rng>(map: &Map, edges: &[BoardEdge], gen: &mut R) -> HexNetwork{
+        generate_rivers_with(map, edges, |_| Some(1), gen)
+    }
+
+    /// Generate rivers like `generate_rivers`, with the main course taking the cheapest way under `cost`
+    pub fn generate_rivers_with<R, F>(map: &Map, edges: &[BoardEdge], cost: F, gen: &mut R) -> HexNetwork
+    where R: Rng, F: Fn((usize, usize)) -> Option<u32>{
+        let mut network = HexNetwork::new();
+        let (first, rest) = match edges.split_first(){
+            Some(e) => e,
+            None => return network,
+        };
+
+        // main course
+        let second = rest.first().copied().unwrap_or(first.opposite());
+        let start = random_edge_location(map, *first, gen);
+        let targets = edge_targets(map, second, start);
+        if let Some(path) = cheapest_path(map, start, &targets, cost, gen){
+            network.add_path(map, &path);
+            network.add_exit(map, start, *first);
+            network.add_exit(map, *path.last().expect("Path is never empty"), second);
+        }
+
+        // branches joining the main course
+        for edge in rest.iter().skip(1){
+            let start = random_edge_location(map, *edge, gen);
+            if network.contains(start){
+                network.add_exit(map, start, *edge);
+                continue;
+            }
+            let targets = network.locations();
+            if let Some(path) = cheapest_path(map, start, &targets, |_| Some(1), gen){
+                network.add_path(map, &path);
+                network.add_exit(map, start, *edge);
+            }
+        }
+        network
+    }
+
+    /// Place river crossings on the map's rivers
+    /// Every road crossing a river gets a bridge, stone next to a town or outpost and wood elsewhere,
+    /// and fords are spread along shallow stretches. A campaign tile with a bridge or ford add-on
+    /// guarantees at least one of that crossing on the map.
+    pub fn generate_crossings<R: Rng>(map: &Map, tiles: &CampaignGenerationTiles, gen: &mut R) -> Vec<((usize, usize), RiverCrossing)>{
+        let rivers = map.get_rivers();
+        let mut crossings = vec![];
+        if rivers.is_empty(){
+            return crossings;
+        }
+
+        // bridges carrying roads
+        for l in rivers.locations(){
+            if !map.get_roads().contains(l){
+                continue;
+            }
+            let near_settlement = map.neighbor_locations(l.0, l.1).iter()
+                .filter_map(|(_, n)| map.get_tile(n.0, n.1))
+                .any(|t| matches!(t.get_type(), BattleMapTileType::Town | BattleMapTileType::Outpost));
+            crossings.push((l, match near_settlement{
+                true => RiverCrossing::BridgeStone,
+                false => RiverCrossing::BridgeWood,
+            }));
+        }
+
+        let campaign_has = |add_on: CampaignMapTileAddOn| BoardEdge::ALL.iter()
+            .any(|e| e.campaign_tile(tiles).get_add_on() == Some(&add_on));
+
+        // a campaign bridge with no road over the river still leaves a bridge standing
+        let mut shallows = shallow_locations(map, &crossings);
+        if campaign_has(CampaignMapTileAddOn::RiverBridge) && crossings.is_empty(){
+            let candidates = match shallows.is_empty(){
+                true => rivers.locations(),
+                false => shallows.clone(),
+            };
+            let l = candidates[random_index(gen, candidates.len())];
+            crossings.push((l, RiverCrossing::BridgeWood));
+            shallows = shallow_locations(map, &crossings);
+        }
+
+        // fords
+        let mut ford_ct = rivers.len() / RIVER_TILES_PER_FORD;
+        if campaign_has(CampaignMapTileAddOn::RiverFord){
+            ford_ct = ford_ct.max(1);
+        }
+        for i in 0..ford_ct{
+            // a campaign ford is kept even where the river has no shallows
+            let candidates = match (shallows.is_empty(), i == 0 && campaign_has(CampaignMapTileAddOn::RiverFord)){
+                (false, _) => shallows.clone(),
+                (true, true) => rivers.locations().into_iter().filter(|l| crossings.iter().all(|(c, _)| c != l)).collect(),
+                (true, false) => break,
+            };
+            if candidates.is_empty(){
+                break;
+            }
+            let l = candidates[random_index(gen, candidates.len())];
+            crossings.push((l, RiverCrossing::Ford));
+            shallows = shallow_locations(map, &crossings);
+        }
+        crossings
+    }
+
+    /// River hexes shallow enough to ford, a single channel with no high ground on either bank,
+    /// away from any existing crossing
+    fn shallow_locations(map: &Map, crossings: &[((usize, usize), RiverCrossing)]) -> Vec<(usize, usize)>{
+        let rivers = map.get_rivers();
+        rivers.locations().into_iter()
+            .filter(|l| rivers.get_connections(*l).len() == 2)
+            .filter(|l| !crossings.iter().any(|(c, _)| c == l || map.direction_to(*c, *l).is_some()))
+            .filter(|l| !map.neighbor_locations(l.0, l.1).iter()
+                .filter_map(|(_, n)| map.get_tile(n.0, n.1))
+                .any(|t| matches!(t.get_type(), BattleMapTileType::Hill | BattleMapTileType::Mountain)))
+            .collect()
+    }
+
+}
+
+
+pub mod roads{
+
+    use crate::battle_map::{BoardEdge, HexDirection, Map};
+    use crate::hex_paths::{cheapest_path, edge_targets, random_edge_location, HexNetwork, PathShape};
+    use crate::map_tiles::{BattleMapTileType, CampaignGenerationTiles};
+    use rand::Rng;
+
+    /// Shape of a road hex, from the tiles available in the base game
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum RoadTileType{
+        Straight, // has 3 rotations
+        Turn120, // has 6 rotations
+        BranchY, // has 2 rotations
+        Branch120, // has 6 rotations
+    }
+
+    impl RoadTileType{
+        /// Shape of a road hex connecting in the given directions, None for a road ending in the hex
+        pub fn from_connections(connections: &[HexDirection]) -> Option<Self>{
+            match PathShape::from_connections(connections){
+                PathShape::End => None,
+                PathShape::Straight => Some(RoadTileType::Straight),
+                PathShape::Turn120 => Some(RoadTileType::Turn120),
+                PathShape::BranchY => Some(RoadTileType::BranchY),
+                PathShape::Branch120 => Some(RoadTileType::Branch120),
+            }
+        }
+    }
+
+    /// Shape of the road at a location on the map, None if there is no road tile there
+    pub fn road_tile_type(map: &Map, location: (usize, usize)) -> Option<RoadTileType>{
+        match map.get_roads().contains(location){
+            true => RoadTileType::from_connections(map.get_roads().get_connections(location)),
+            false => None,
+        }
+    }
+
+    /// Edges of the battle map that a road must cross, from the campaign tiles carrying a road
+    pub fn road_edges(tiles: &CampaignGenerationTiles) -> Vec<BoardEdge>{
+        BoardEdge::ALL.iter()
+            .copied()
+            .filter(|e| e.campaign_tile(tiles).has_road())
+            .collect()
+    }
+
+    /// Cost of building a road through a tile, roads keep to open ground where they can
+    pub fn road_cost(t_type: &BattleMapTileType) -> u32{
+        match t_type{
+            BattleMapTileType::Road
+            | BattleMapTileType::Town
+            | BattleMapTileType::Outpost => 1,
+            BattleMapTileType::Plains
+            | BattleMapTileType::Default => 2,
+            BattleMapTileType::Hill
+            | BattleMapTileType::Forest => 4,
+            BattleMapTileType::River => 8,
+            BattleMapTileType::Swamp => 10,
+            BattleMapTileType::Mountain => 16,
+        }
+    }
+
+    /// Tiles that keep their own type when a road runs through them
+    pub fn keeps_type_under_road(t_type: &BattleMapTileType) -> bool{
+        matches!(t_type, BattleMapTileType::Town | BattleMapTileType::Outpost | BattleMapTileType::River)
+    }
+
+    /// Generate a road network crossing the given edges and reaching every town and outpost
+    /// A single edge with no towns runs to the opposite edge, otherwise each town and then each
+    /// further edge is joined to the closest part of the network along the cheapest terrain
+    pub fn generate_roads<R: Rng>(map: &Map, edges: &[BoardEdge], gen: &mut R) -> HexNetwork{
+        let mut network = HexNetwork::new();
+        let mut settlements = map.locations_of(&BattleMapTileType::Town);
+        settlements.extend(map.locations_of(&BattleMapTileType::Outpost));
+
+        let cost = |network: &HexNetwork, l: (usize, usize)| match network.contains(l){
+            true => Some(1),
+            false => map.get_tile(l.0, l.1).map(|t| road_cost(t.get_type())),
+        };
+
+        // seed the network from the first edge, or the first settlement if no road leaves the map
+        let mut remaining_edges = edges.iter().copied();
+        match remaining_edges.next(){
+            Some(edge) => {
+                let start = random_edge_location(map, edge, gen);
+                network.add_location(start);
+                network.add_exit(map, start, edge);
+                if settlements.is_empty(){
+                    let target = edges.get(1).copied().unwrap_or(edge.opposite());
+                    let targets = edge_targets(map, target, start);
+                    if let Some(path) = cheapest_path(map, start, &targets, |l| cost(&network, l), gen){
+                        let end = *path.last().expect("Path is never empty");
+                        network.add_path(map, &path);
+                        network.add_exit(map, end, target);
+                    }
+                    remaining_edges.next();
+                }
+            },
+            None => match settlements.first(){
+                Some(s) => network.add_location(*s),
+                None => return network,
+            },
+        }
+
+        // towns first so roads between edges run through them
+        connect_locations(map, &mut network, &settlements, gen);
+        for edge in remaining_edges{
+            let start = random_edge_location(map, edge, gen);
+            if !network.contains(start){
+                if let Some(path) = cheapest_path(map, start, &network.locations(), |l| cost(&network, l), gen){
+                    network.add_path(map, &path);
+                }
+            }
+            network.add_exit(map, start, edge);
+        }
+        network
+    }
+
+    /// Run a road from `location` off the map across `edge` along the cheapest terrain
+    pub fn connect_to_edge<R: Rng>(map: &Map, network: &mut HexNetwork, location: (usize, usize), edge: BoardEdge, gen: &mut R){
+        network.add_location(location);
+        if map.edge_locations(edge).contains(&location){
+            network.add_exit(map, location, edge);
+            return;
+        }
+        let targets = edge_targets(map, edge, location);
+        let cost = |l: (usize, usize)| map.get_tile(l.0, l.1).map(|t| road_cost(t.get_type()));
+        if let Some(path) = cheapest_path(map, location, &targets, cost, gen){
+            let end = *path.last().expect("Path is never empty");
+            network.add_path(map, &path);
+            network.add_exit(map, end, edge);
+        }
+    }
+
+    /// Join each location to the closest part of the road network along the cheapest terrain,
+    /// an empty network starts from the first location
+    pub fn connect_locations<R: Rng>(map: &Map, network: &mut HexNetwork, locations: &[(usize, usize)], gen: &mut R){
+        let cost = |network: &HexNetwork, l: (usize, usize)| match network.contains(l){
+            true => Some(1),
+            false => map.get_tile(l.0, l.1).map(|t| road_cost(t.get_type())),
+        };
+        for l in locations.iter(){
+            if network.is_empty(){
+                network.add_location(*l);
+            }
+            if network.contains(*l){
+                continue;
+            }
+            if let Some(path) = cheapest_path(map, *l, &network.locations(), |n| cost(network, n), gen){
+                network.add_path(map, &path);
+            }
+        }
+    }
+
+}
+
+
+pub mod towns{
+
+    use crate::battle_map::Map;
+    use crate::clusters::weighted_index;
+    use crate::hex_paths::HexNetwork;
+    use crate::map_tiles::{BattleMapTileType, CampaignGenerationTiles, CampaignMapTileType, TileOwner};
+    use rand::Rng;
+    use std::cmp::Reverse;
+
+    /// Most hexes a single settlement covers
+    pub const MAX_TOWN_HEXES: usize = 7;
+
+    /// Hexes in each settlement, larger the more densely populated the campaign area
+    pub fn town_size(city_density: f64) -> usize{
+        (1 + (city_density.max(0.0) * 12.0).round() as usize).min(MAX_TOWN_HEXES)
+    }
+
+    /// Owners whose campaign tile is a town, settlements keep to their regions when there are any
+    pub fn town_owners(tiles: &CampaignGenerationTiles) -> Vec<TileOwner>{
+        [
+            (TileOwner::Attacker, tiles.get_attacker()),
+            (TileOwner::Defender, tiles.get_defender()),
+            (TileOwner::LeftFlank, tiles.get_left_flank()),
+            (TileOwner::RightFlank, tiles.get_right_flank()),
+        ].into_iter()
+            .filter(|(_, t)| t.get_type() == &CampaignMapTileType::Town)
+            .map(|(o, _)| o)
+            .collect()
+    }
+
+    /// How strongly a settlement is drawn to a hex, most to flat ground on or beside a road and beside a river
+    pub fn town_weight(map: &Map, location: (usize, usize), roads: &HexNetwork) -> u32{
+        let height = map.get_tile(location.0, location.1).map_or(0, |t| t.get_elevation());
+        let neighbors = map.neighbor_locations(location.0, location.1);
+        let slope = neighbors.iter()
+            .filter_map(|(_, n)| map.get_tile(n.0, n.1))
+            .map(|t| t.get_elevation().abs_diff(height))
+            .max()
+            .unwrap_or(0);
+        let roadside = roads.contains(location) || neighbors.iter().any(|(_, n)| roads.contains(*n));
+        let riverside = neighbors.iter()
+            .any(|(_, n)| map.get_tile(n.0, n.1).is_some_and(|t| t.get_type() == &BattleMapTileType::River));
+        1 + 2 * 4u32.saturating_sub(slope)
+            + match roadside{ true => 8, false => 0 }
+            + match riverside{ true => 4, false => 0 }
+    }
+
+    /// Place compact settlements of up to `size` hexes on the free tiles until `count` hexes are town,
+    /// with at least one in each of the `owners` regions and none outside them when any are given
+    /// Settlements never touch each other and the first hex of each is its centre
+    pub fn place_towns<R: Rng>(map: &Map, free: &[(usize, usize)], owners: &[TileOwner], count: usize, size: usize, roads: &HexNetwork, gen: &mut R) -> Vec<Vec<(usize, usize)>>{
+        let owner_of = |l: &(usize, usize)| map.get_tile(l.0, l.1).expect("Free tiles must be on the board").get_owner();
+        let mut candidates: Vec<(usize, usize)> = free.iter()
+            .copied()
+            .filter(|l| owners.is_empty() || owners.iter().any(|o| in_region(owner_of(l), o)))
+            .collect();
+        let mut guaranteed = owners.iter();
+        let mut settlements: Vec<Vec<(usize, usize)>> = vec![];
+        let mut placed = 0;
+        loop{
+            let region = guaranteed.next();
+            if region.is_none() && placed >= count{
+                break;
+            }
+            let in_bounds = |l: &(usize, usize)| region.is_none_or(|o| in_region(owner_of(l), o));
+            let apart = |l: &(usize, usize), settlements: &[Vec<(usize, usize)>]| settlements.iter().flatten().all(|s| map.distance(*s, *l) > 1);
+
+            let seeds: Vec<(usize, usize)> = candidates.iter().copied().filter(|l| in_bounds(l) && apart(l, &settlements)).collect();
+            if seeds.is_empty(){
+                match region{
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+            let weights: Vec<u32> = seeds.iter().map(|l| town_weight(map, *l, roads)).collect();
+            let center = seeds[weighted_index(&weights, gen)];
+            let height = map.get_tile(center.0, center.1).map_or(0, |t| t.get_elevation());
+
+            // grow into the free neighbor most surrounded by the town, then the one most level with its centre
+            let target = size.min(count.saturating_sub(placed)).max(1);
+            let mut hexes = vec![center];
+            while hexes.len() < target{
+                let mut options: Vec<(usize, usize)> = hexes.iter()
+                    .flat_map(|h| map.neighbor_locations(h.0, h.1))
+                    .map(|(_, n)| n)
+                    .filter(|n| candidates.contains(n) && !hexes.contains(n) && in_bounds(n) && apart(n, &settlements))
+                    .collect();
+                options.dedup();
+                let best = options.into_iter().max_by_key(|n| (
+                    map.neighbor_locations(n.0, n.1).iter().filter(|(_, x)| hexes.contains(x)).count(),
+                    Reverse(map.get_tile(n.0, n.1).map_or(0, |t| t.get_elevation()).abs_diff(height)),
+                    Reverse(*n),
+                ));
+                match best{
+                    Some(n) => hexes.push(n),
+                    None => break,
+                }
+            }
+            candidates.retain(|l| !hexes.contains(l));
+            placed += hexes.len();
+            settlements.push(hexes);
+        }
+        settlements
+    }
+
+    /// Whether a tile owned by `owner` lies in `region`, the split row belonging to both attacker and defender
+    fn in_region(owner: &TileOwner, region: &TileOwner) -> bool{
+        owner == region || (owner == &TileOwner::SplitAttDef && matches!(region, TileOwner::Attacker | TileOwner::Defender))
+    }
+
+}
+
+pub mod pathfinding{
+
+    use crate::battle_map::Map;
+    use crate::coords::HexCoord;
+    use crate::map_tiles::{BattleMapTileType, MapTile, RiverCrossing};
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    /// Movement points needed to enter each tile, None where units cannot go
+    /// A river crossing on a tile replaces the tile's own cost
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct MovementCosts{
+        tiles: Vec<(BattleMapTileType, Option<u32>)>,
+        crossings: Vec<(RiverCrossing, Option<u32>)>,
+        climb: u32,
+    }
+
+    impl Default for MovementCosts{
+        /// Roads are quickest, swamps and mountains slow, and rivers can only be crossed at fords and bridges
+        fn default() -> Self{
+            MovementCosts {
+                tiles: vec![
+                    (BattleMapTileType::Road, Some(1)),
+                    (BattleMapTileType::Plains, Some(2)),
+                    (BattleMapTileType::Town, Some(2)),
+                    (BattleMapTileType::Outpost, Some(2)),
+                    (BattleMapTileType::Default, Some(2)),
+                    (BattleMapTileType::Forest, Some(3)),
+                    (BattleMapTileType::Hill, Some(3)),
+                    (BattleMapTileType::Swamp, Some(5)),
+                    (BattleMapTileType::Mountain, Some(6)),
+                    (BattleMapTileType::River, None),
+                ],
+                crossings: vec![
+                    (RiverCrossing::BridgeStone, Some(1)),
+                    (RiverCrossing::BridgeWood, Some(1)),
+                    (RiverCrossing::Ford, Some(4)),
+                ],
+                climb: 1,
+            }
+        }
+    }
+
+    impl MovementCosts{
+        /// Set the cost of entering a tile type, None makes it impassable
+        pub fn set_cost(&mut self, t_type: BattleMapTileType, cost: Option<u32>){
+            match self.tiles.iter_mut().find(|(t, _)| *t == t_type){
+                Some(entry) => entry.1 = cost,
+                None => self.tiles.push((t_type, cost)),
+            }
+        }
+
+        /// Set the cost of entering a tile with a river crossing, None makes it impassable
+        pub fn set_crossing_cost(&mut self, crossing: RiverCrossing, cost: Option<u32>){
+            match self.crossings.iter_mut().find(|(c, _)| *c == crossing){
+                Some(entry) => entry.1 = cost,
+                None => self.crossings.push((crossing, cost)),
+            }
+        }
+
+        /// Cost of entering a tile type, ignoring any crossing
+        pub fn get_cost(&self, t_type: &BattleMapTileType) -> Option<u32>{
+            self.tiles.iter().find(|(t, _)| t == t_type).and_then(|(_, c)| *c)
+        }
+
+        /// Cost of entering a tile, from its crossing if it has one and otherwise its type
+        pub fn tile_cost(&self, tile: &MapTile) -> Option<u32>{
+            match tile.get_crossing(){
+                Some(crossing) => self.crossings.iter().find(|(c, _)| c == crossing).and_then(|(_, c)| *c),
+                None => self.get_cost(tile.get_type()),
+            }
+        }
+
+        /// Set the extra cost for each elevation level climbed
+        pub fn set_climb_cost(&mut self, climb: u32){
+            self.climb = climb;
+        }
+
+        pub fn get_climb_cost(&self) -> u32{
+            self.climb
+        }
+
+        /// Cost of moving from one tile onto a neighboring one, including any climb
+        /// None if the tile is impassable or the cost does not fit in a u32
+        pub fn step_cost(&self, from: &MapTile, to: &MapTile) -> Option<u32>{
+            let climb = to.get_elevation().saturating_sub(from.get_elevation());
+            self.tile_cost(to).and_then(|c| c.checked_add(climb.checked_mul(self.climb)?))
+        }
+
+        /// Cheapest cost of any tile, used to estimate remaining cost in A*
+        fn min_cost(&self) -> u32{
+            self.tiles.iter().filter_map(|(_, c)| *c)
+                .chain(self.crossings.iter().filter_map(|(_, c)| *c))
+                .min()
+                .unwrap_or(1)
+        }
+    }
+
+    /// A route across the map and its total movement cost, not counting the starting hex
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct MapPath{
+        hexes: Vec<(usize, usize)>,
+        cost: u32,
+    }
+
+    impl MapPath{
+        /// Hexes along the route, from the start to the goal
+        pub fn get_hexes(&self) -> &[(usize, usize)]{
+            &self.hexes
+        }
+
+        pub fn get_cost(&self) -> u32{
+            self.cost
+        }
+
+        /// Turns needed to walk the route with the given movement points per turn
+        pub fn turns(&self, movement_per_turn: u32) -> u32{
+            self.cost.div_ceil(movement_per_turn.max(1))
+        }
+    }
+
+    /// Cheapest cost from one hex to every other hex on the map
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct CostField{
+        origin: (usize, usize),
+        width: usize,
+        costs: Vec<Option<u32>>,
+        previous: Vec<Option<usize>>,
+    }
+
+    impl CostField{
+        pub fn get_origin(&self) -> (usize, usize){
+            self.origin
+        }
+
+        /// Cheapest cost to reach a hex, None if it cannot be reached
+        pub fn get_cost(&self, row: usize, column: usize) -> Option<u32>{
+            match column < self.width{
+                true => self.costs.get(row * self.width + column).copied().flatten(),
+                false => None,
+            }
+        }
+
+        /// Cheapest route from the origin to a hex, None if it cannot be reached
+        pub fn path_to(&self, row: usize, column: usize) -> Option<MapPath>{
+            let cost = self.get_cost(row, column)?;
+            let mut hexes = vec![(row, column)];
+            let mut current = row * self.width + column;
+            while let Some(p) = self.previous[current]{
+                hexes.push((p / self.width, p % self.width));
+                current = p;
+            }
+            hexes.reverse();
+            Some(MapPath { hexes, cost })
+        }
+    }
+
+    /// Dijkstra's search out from `from` over the whole map
+    pub fn cost_field(map: &Map, from: (usize, usize), costs: &MovementCosts) -> CostField{
+        let width = map.get_board_width();
+        let (c, p) = search(map, from, None, costs);
+        CostField { origin: from, width, costs: c, previous: p }
+    }
+
+    /// Cheapest route between two hexes using A*, None if the goal cannot be reached
+    pub fn find_path(map: &Map, from: (usize, usize), to: (usize, usize), costs: &MovementCosts) -> Option<MapPath>{
+        let width = map.get_board_width();
+        let (c, p) = search(map, from, Some(to), costs);
+        CostField { origin: from, width, costs: c, previous: p }.path_to(to.0, to.1)
+    }
+
+    /// Shared search, stopping early with a distance estimate when there is a goal
+    fn search(map: &Map, from: (usize, usize), goal: Option<(usize, usize)>, costs: &MovementCosts) -> (Vec<Option<u32>>, Vec<Option<usize>>){
+        let width = map.get_board_width();
+        let cells = width * map.get_board_height();
+        let index = |l: (usize, usize)| l.0 * width + l.1;
+        let mut best: Vec<Option<u32>> = vec![None; cells];
+        let mut previous: Vec<Option<usize>> = vec![None; cells];
+        if map.get_tile(from.0, from.1).is_none(){
+            return (best, previous);
+        }
+
+        let min_cost = costs.min_cost();
+        let estimate = |l: (usize, usize)| match goal{
+            Some(g) => HexCoord::from_offset(l.0, l.1).distance(HexCoord::from_offset(g.0, g.1)).saturating_mul(min_cost),
+            None => 0,
+        };
+
+        best[index(from)] = Some(0);
+        let mut heap = BinaryHeap::new();
+        heap.push(Reverse((estimate(from), 0, from)));
+        while let Some(Reverse((_, cost, l))) = heap.pop(){
+            if best[index(l)].is_some_and(|b| b < cost){
+                continue;
+            }
+            if Some(l) == goal{
+                break;
+            }
+            for (_, n) in map.neighbor_locations(l.0, l.1){
+                let here = map.get_tile(l.0, l.1).expect("Searched hexes are on the board");
+                // routes costing more than a u32 holds are treated as impassable
+                let Some(step) = map.get_tile(n.0, n.1).and_then(|t| costs.step_cost(here, t)) else { continue };
+                let Some(next) = cost.checked_add(step) else { continue };
+                if best[index(n)].is_none_or(|b| next < b){
+                    best[index(n)] = Some(next);
+                    previous[index(n)] = Some(index(l));
+                    heap.push(Reverse((next.saturating_add(estimate(n)), next, n)));
+                }
+            }
+        }
+        (best, previous)
+    }
+
+}
+
+pub mod outposts{
+
+    use crate::battle_map::{BoardEdge, Map};
+    use crate::hex_paths::HexNetwork;
+    use crate::map_tiles::{BattleMapTileType, CampaignGenerationTiles, CampaignMapTileType, MapAddOn, TileOwner};
+    use crate::{fortifications, roads};
+    use rand::Rng;
+    use std::cmp::Reverse;
+
+    /// Owners whose campaign tile is an outpost, each gets one on the battle map
+    pub fn outpost_owners(tiles: &CampaignGenerationTiles) -> Vec<TileOwner>{
+        BoardEdge::ALL.iter()
+            .filter(|e| e.campaign_tile(tiles).get_type() == &CampaignMapTileType::Outpost)
+            .map(|e| e.owner(tiles.get_attack_direction()))
+            .collect()
+    }
+
+    /// The most defensible open hex in an owner's region: the highest, then the one looking down on most neighbors
+    pub fn place_outpost(map: &Map, owner: &TileOwner) -> Option<(usize, usize)>{
+        let height = |l: (usize, usize)| map.get_tile(l.0, l.1).map_or(0, |t| t.get_elevation());
+        (0..map.get_board_height())
+            .flat_map(|r| (0..map.get_board_width()).map(move |c| (r, c)))
+            .filter(|l| map.get_tile(l.0, l.1).is_some_and(|t|
+                t.get_owner() == owner && matches!(t.get_type(), BattleMapTileType::Default | BattleMapTileType::Hill)))
+            .max_by_key(|l| (
+                height(*l),
+                map.neighbor_locations(l.0, l.1).iter().filter(|(_, n)| height(*n) < height(*l)).count(),
+                Reverse(*l),
+            ))
+    }
+
+    /// Join an outpost to the road network, or run a road off the owner's edge when no road leaves the map yet
+    /// The owner's edge is the one it holds most of, or the one facing its campaign tile if its region reaches no edge
+    pub fn connect_outpost<R: Rng>(map: &Map, network: &mut HexNetwork, outpost: (usize, usize), owner: &TileOwner, gen: &mut R){
+        let has_exit = network.locations().iter().any(|l| network.is_exit(map, *l));
+        let edge = map.edge_of_owner(owner).or_else(|| BoardEdge::of_owner(owner, map.get_campaign_tiles().get_attack_direction()));
+        match (has_exit, edge){
+            (false, Some(edge)) => roads::connect_to_edge(map, network, outpost, edge, gen),
+            _ => roads::connect_locations(map, network, &[outpost], gen),
+        }
+    }
+
+    /// A palisade on every outpost and barricades on each buildable hex around it
+    pub fn outpost_defences(map: &Map) -> Vec<((usize, usize), MapAddOn)>{
+        let mut defences = vec![];
+        for outpost in map.locations_of(&BattleMapTileType::Outpost){
+            defences.push((outpost, MapAddOn::Palisade));
+            for (_, n) in map.neighbor_locations(outpost.0, outpost.1){
+                if map.get_tile(n.0, n.1).is_some_and(|t| t.get_type() != &BattleMapTileType::Outpost && fortifications::fortifiable(t)){
+                    defences.push((n, MapAddOn::Barricade));
+                }
+            }
+        }
+        defences
+    }
+
+}
+
+pub mod fortifications{
+
+    use crate::battle_map::Map;
+    use crate::clusters::weighted_index;
+    use crate::map_tiles::{BattleMapTileType, CampaignGenerationTiles, CampaignMapTileType, MapAddOn, MapTile, TileOwner};
+    use crate::pathfinding::MovementCosts;
+    use rand::Rng;
+
+    /// Share of the defender's region fortified, most for an outpost garrison and least for open country
+    pub fn fortified_share(defender: &CampaignMapTileType) -> f64{
+        match defender{
+            CampaignMapTileType::Outpost => 0.3,
+            CampaignMapTileType::Town => 0.2,
+            _ => 0.05,
+        }
+    }
+
+    /// Fortification suited to a tile: barricades across roads and bridges, walls around towns,
+    /// palisades around outposts and trenches in the open
+    pub fn add_on_for(tile: &MapTile, defender: &CampaignMapTileType) -> MapAddOn{
+        match tile.get_type(){
+            _ if tile.get_crossing().is_some() => MapAddOn::Barricade,
+            BattleMapTileType::Road => MapAddOn::Barricade,
+            BattleMapTileType::Town => MapAddOn::Wall,
+            BattleMapTileType::Outpost => MapAddOn::Palisade,
+            _ => match defender{
+                CampaignMapTileType::Outpost => MapAddOn::Palisade,
+                _ => MapAddOn::Trench,
+            },
+        }
+    }
+
+    /// How much a hex is worth fortifying, more where slow or impassable ground hems it in,
+    /// on roads and beside river crossings
+    pub fn chokepoint_score(map: &Map, location: (usize, usize)) -> u32{
+        let costs = MovementCosts::default();
+        let neighbors = map.neighbor_locations(location.0, location.1);
+        let hemmed = neighbors.iter()
+            .filter(|(_, n)| map.get_tile(n.0, n.1).and_then(|t| costs.tile_cost(t)).is_none_or(|c| c >= 5))
+            .count() as u32;
+        let crossing = neighbors.iter()
+            .any(|(_, n)| map.get_tile(n.0, n.1).is_some_and(|t| t.get_crossing().is_some()));
+        1 + 3 * hemmed
+            + match map.get_roads().contains(location){ true => 6, false => 0 }
+            + match crossing{ true => 4, false => 0 }
+    }
+
+    /// Fortifications for the defender's region, drawn towards chokepoints and roads
+    /// Mountains and open water are never fortified
+    pub fn fortify<R: Rng>(map: &Map, tiles: &CampaignGenerationTiles, gen: &mut R) -> Vec<((usize, usize), MapAddOn)>{
+        let defender = tiles.get_defender().get_type();
+        let mut candidates: Vec<(usize, usize)> = (0..map.get_board_height())
+            .flat_map(|r| (0..map.get_board_width()).map(move |c| (r, c)))
+            .filter(|l| map.get_tile(l.0, l.1).is_some_and(|t| t.get_owner() == &TileOwner::Defender && fortifiable(t)))
+            .collect();
+        let count = (fortified_share(defender) * candidates.len() as f64).round() as usize;
+
+        let mut weights: Vec<u32> = candidates.iter().map(|l| chokepoint_score(map, *l)).collect();
+        let mut fortified = vec![];
+        while fortified.len() < count && !candidates.is_empty(){
+            let i = weighted_index(&weights, gen);
+            let l = candidates.remove(i);
+            weights.remove(i);
+            let tile = map.get_tile(l.0, l.1).expect("Candidates are on the board");
+            fortified.push((l, add_on_for(tile, defender)));
+        }
+        fortified
+    }
+
+    /// Whether anything can be built on a tile
+    pub fn fortifiable(tile: &MapTile) -> bool{
+        match tile.get_type(){
+            BattleMapTileType::Mountain => false,
+            BattleMapTileType::River => tile.get_crossing().is_some(),
+            _ => true,
+        }
+    }
+
+}
+
+pub mod visibility{
+
+    use crate::battle_map::Map;
+    use crate::coords::MapLocation;
+    use crate::map_tiles::{BattleMapTileType, MapTile};
+
+    /// How a tile type affects sight: `elevation` raises anyone standing on it and
+    /// `cover` is how far its trees or buildings rise above the ground, both on top of the tile's own elevation
+    /// A hex between two others blocks sight when its height plus cover is higher
+    /// than the higher of the two ends
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct SightRules{
+        tiles: Vec<(BattleMapTileType, u32, u32)>,
+    }
+
+    impl Default for SightRules{
+        /// Forests, towns and mountains block sight, and hills see over forests and towns
+        fn default() -> Self{
+            SightRules {
+                tiles: vec![
+                    (BattleMapTileType::Hill, 1, 0),
+                    (BattleMapTileType::Mountain, 2, 0),
+                    (BattleMapTileType::Forest, 0, 1),
+                    (BattleMapTileType::Town, 0, 1),
+                ],
+            }
+        }
+    }
+
+    impl SightRules{
+        /// Set the elevation and cover of a tile type
+        pub fn set_rule(&mut self, t_type: BattleMapTileType, elevation: u32, cover: u32){
+            match self.tiles.iter_mut().find(|(t, _, _)| *t == t_type){
+                Some(entry) => { entry.1 = elevation; entry.2 = cover; },
+                None => self.tiles.push((t_type, elevation, cover)),
+            }
+        }
+
+        /// Height a viewer standing on the tile type sees from
+        pub fn get_elevation(&self, t_type: &BattleMapTileType) -> u32{
+            self.tiles.iter().find(|(t, _, _)| t == t_type).map_or(0, |(_, e, _)| *e)
+        }
+
+        /// Height the tile type's trees or buildings add above its ground
+        pub fn get_cover(&self, t_type: &BattleMapTileType) -> u32{
+            self.tiles.iter().find(|(t, _, _)| t == t_type).map_or(0, |(_, _, c)| *c)
+        }
+
+        /// Height a viewer standing on the tile sees from
+        fn eye(&self, tile: &MapTile) -> u32{
+            tile.get_elevation() + self.get_elevation(tile.get_type())
+        }
+
+        /// Height of the top of anything on the tile
+        fn obstruction(&self, tile: &MapTile) -> u32{
+            self.eye(tile) + self.get_cover(tile.get_type())
+        }
+    }
+
+    impl Map{
+        /// Whether the two hexes can see each other, false if either is off the board
+        pub fn line_of_sight<A: MapLocation, B: MapLocation>(&self, from: A, to: B, rules: &SightRules) -> bool{
+            let (from, to) = (from.coord(), to.coord());
+            let (Some(a), Some(b)) = (self.get_tile_at(from), self.get_tile_at(to)) else { return false };
+            let eye = rules.eye(a).max(rules.eye(b));
+            let line = from.line_to(to);
+            line.iter().skip(1).take(line.len().saturating_sub(2))
+                .all(|h| self.get_tile_at(*h).is_some_and(|t| rules.obstruction(t) <= eye))
+        }
+
+        /// Which hexes can be seen from `from`, row-major
+        pub fn visibility_map<C: MapLocation>(&self, from: C, rules: &SightRules) -> Vec<bool>{
+            let from = from.coord();
+            self.coords().into_iter().map(|h| self.line_of_sight(from, h, rules)).collect()
+        }
+    }
+
+}
+
+pub mod wfc{
+    //! A wave function collapse solver for declarative tile rule sets.
+    //! `MapGenerator::set_wfc_terrain` fills the free ground around the placed rivers, roads and
+    //! settlements with `battle_terrain_rules`, and callers can lay out their own tiles over a map's hexes.
+
+    use crate::battle_map::{random_index, HexDirection, Map};
+    use crate::errors::MapGenError;
+    use crate::map_tiles::BattleMapTileType;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::BTreeSet;
+
+    /// Largest number of distinct tiles a rule set can hold
+    pub const MAX_WFC_TILES: usize = 64;
+
+    /// Backtracking steps allowed before a solve gives up
+    pub const DEFAULT_MAX_BACKTRACKS: usize = 10_000;
+
+    /// Declarative wave function collapse rules: the tiles that can be placed, how likely each is,
+    /// and which tiles may sit next to each other in each of the six hex directions
+    #[derive(Clone, Debug)]
+    pub struct WfcRules<T>{
+        tiles: Vec<T>,
+        weights: Vec<f64>,
+        adjacency: Vec<[u64; 6]>, // per tile and direction, mask of tiles allowed in that direction
+    }
+
+    impl<T: Clone + PartialEq> Default for WfcRules<T>{
+        fn default() -> Self{
+            WfcRules { tiles: vec![], weights: vec![], adjacency: vec![] }
+        }
+    }
+
+    impl<T: Clone + PartialEq> WfcRules<T>{
+        pub fn new() -> Self{
+            WfcRules::default()
+        }
+
+        /// Add a tile with its relative weight, adding an existing tile updates its weight
+        /// Fails once the rules already hold `MAX_WFC_TILES` tiles
+        pub fn add_tile(&mut self, tile: T, weight: f64) -> Result<(), MapGenError>{
+            match self.index_of(&tile){
+                Some(i) => self.weights[i] = weight,
+                None if self.tiles.len() >= MAX_WFC_TILES => return Err(MapGenError::TooManyTiles { max: MAX_WFC_TILES }),
+                None => {
+                    self.tiles.push(tile);
+                    self.weights.push(weight);
+                    self.adjacency.push([0; 6]);
+                }
+            }
+            Ok(())
+        }
+
+        /// Allow `b` to sit in `direction` from `a`, which also allows `a` in the opposite direction from `b`
+        /// Fails if either tile has not been added
+        pub fn allow(&mut self, a: &T, direction: HexDirection, b: &T) -> Result<(), MapGenError>{
+            let (ai, bi) = self.index_of(a).zip(self.index_of(b)).ok_or(MapGenError::UnknownTile)?;
+            self.adjacency[ai][direction.index()] |= 1 << bi;
+            self.adjacency[bi][direction.opposite().index()] |= 1 << ai;
+            Ok(())
+        }
+
+        /// Allow `a` and `b` next to each other in every direction
+        pub fn allow_all(&mut self, a: &T, b: &T) -> Result<(), MapGenError>{
+            for d in HexDirection::ALL{
+                self.allow(a, d, b)?;
+            }
+            Ok(())
+        }
+
+        pub fn get_tiles(&self) -> &[T]{
+            &self.tiles
+        }
+
+        /// Weight of a tile, None if it has not been added
+        pub fn get_weight(&self, tile: &T) -> Option<f64>{
+            self.index_of(tile).map(|i| self.weights[i])
+        }
+
+        /// Whether `b` may sit in `direction` from `a`, never for tiles that have not been added
+        pub fn is_allowed(&self, a: &T, direction: HexDirection, b: &T) -> bool{
+            self.index_of(a).zip(self.index_of(b))
+                .is_some_and(|(ai, bi)| self.adjacency[ai][direction.index()] & (1 << bi) != 0)
+        }
+
+        fn index_of(&self, tile: &T) -> Option<usize>{
+            self.tiles.iter().position(|t| t == tile)
+        }
+
+        fn all_tiles_mask(&self) -> u64{
+            match self.tiles.len(){
+                MAX_WFC_TILES => u64::MAX,
+                n => (1 << n) - 1,
+            }
+        }
+    }
+
+    /// Rules for natural terrain: mountains rise out of hills, swamps sit in low ground beside forest,
+    /// and plains, rivers, roads, towns and outposts may border anything else
+    /// Rivers, roads, towns and outposts have no weight, so they are only placed where a hex is restricted to them
+    pub fn battle_terrain_rules() -> WfcRules<BattleMapTileType>{
+        use BattleMapTileType::*;
+        let mut rules = WfcRules::new();
+        let anything = vec![Plains, Forest, Hill, Mountain, Swamp, River, Road, Town, Outpost];
+        let neighbors = [
+            (Plains, 4.0, anything.clone()),
+            (Forest, 2.0, vec![Forest, Hill, Swamp]),
+            (Hill, 2.0, vec![Hill, Mountain]),
+            (Mountain, 1.0, vec![Mountain]),
+            (Swamp, 1.0, vec![Swamp]),
+            (River, 0.0, anything.clone()),
+            (Road, 0.0, anything.clone()),
+            (Town, 0.0, anything.clone()),
+            (Outpost, 0.0, anything),
+        ];
+        for (t, w, _) in neighbors.iter(){
+            rules.add_tile(t.clone(), *w).expect("Battle terrain has fewer tiles than MAX_WFC_TILES");
+        }
+        for (a, _, bs) in neighbors.iter(){
+            for b in bs{
+                rules.allow_all(a, b).expect("Battle terrain neighbors are all added");
+            }
+        }
+        rules
+    }
+
+    /// Solves a set of rules over the hexes of a map
+    pub struct WfcSolver<T>{
+        rules: WfcRules<T>,
+        restrictions: Vec<((usize, usize), Vec<T>)>,
+        max_backtracks: usize,
+    }
+
+    /// A choice made while solving, kept so it can be undone
+    struct WfcFrame{
+        trail: usize, // length of the trail before the choice
+        cell: usize,
+        choice: u64,
+    }
+
+    /// The tiles each hex may still take, the earlier domains of changed hexes so changes can be undone,
+    /// and the undecided hexes ordered by how many tiles they may take, ties in a random order
+    struct WfcState{
+        domains: Vec<u64>,
+        trail: Vec<(usize, u64)>,
+        open: BTreeSet<(u32, u64, usize)>,
+        ties: Vec<u64>,
+    }
+
+    impl WfcState{
+        fn set(&mut self, cell: usize, domain: u64){
+            let old = std::mem::replace(&mut self.domains[cell], domain);
+            self.trail.push((cell, old));
+            self.reorder(cell, old, domain);
+        }
+
+        /// Put back every domain changed since the trail was `trail` long
+        fn undo(&mut self, trail: usize){
+            for (cell, old) in self.trail.split_off(trail).into_iter().rev(){
+                let current = std::mem::replace(&mut self.domains[cell], old);
+                self.reorder(cell, current, old);
+            }
+        }
+
+        fn reorder(&mut self, cell: usize, old: u64, new: u64){
+            if old.count_ones() > 1{
+                self.open.remove(&(old.count_ones(), self.ties[cell], cell));
+            }
+            if new.count_ones() > 1{
+                self.open.insert((new.count_ones(), self.ties[cell], cell));
+            }
+        }
+    }
+
+    impl<T: Clone + PartialEq> WfcSolver<T>{
+        pub fn new(rules: WfcRules<T>) -> Self{
+            WfcSolver { rules, restrictions: vec![], max_backtracks: DEFAULT_MAX_BACKTRACKS }
+        }
+
+        /// Limit a hex of `map` to the given tiles, e.g. to keep rivers already placed on the map
+        /// Fails if the hex is not on the map or a tile is not in the rules
+        pub fn restrict(&mut self, map: &Map, location: (usize, usize), tiles: &[T]) -> Result<(), MapGenError>{
+            if map.get_tile(location.0, location.1).is_none(){
+                return Err(MapGenError::OutOfBounds {
+                    row: location.0, column: location.1, width: map.get_board_width(), height: map.get_board_height()
+                });
+            }
+            if tiles.iter().any(|t| self.rules.index_of(t).is_none()){
+                return Err(MapGenError::UnknownTile);
+            }
+            self.restrictions.push((location, tiles.to_vec()));
+            Ok(())
+        }
+
+        pub fn set_max_backtracks(&mut self, max_backtracks: usize){
+            self.max_backtracks = max_backtracks;
+        }
+
+        /// Collapse every hex of the map to a tile that satisfies the rules with all six neighbors,
+        /// returned in board order (row * width + column). The same seed always gives the same result.
+        /// Returns None if the rules cannot be satisfied within the backtracking limit,
+        /// or a restriction lies off this map.
+        pub fn solve(&self, map: &Map, seed: u64) -> Option<Vec<T>>{
+            let width = map.get_board_width();
+            let cells = width * map.get_board_height();
+            let mut gen = ChaCha8Rng::seed_from_u64(seed);
+            let all = self.rules.all_tiles_mask();
+            let ties: Vec<u64> = (0..cells).map(|_| gen.gen()).collect();
+            let open = match all.count_ones() > 1{
+                true => (0..cells).map(|i| (all.count_ones(), ties[i], i)).collect(),
+                false => BTreeSet::new(),
+            };
+            let mut state = WfcState { domains: vec![all; cells], trail: vec![], open, ties };
+
+            for ((r, c), tiles) in self.restrictions.iter(){
+                map.get_tile(*r, *c)?;
+                let mask = tiles.iter().filter_map(|t| self.rules.index_of(t)).fold(0, |m, t| m | 1 << t);
+                let cell = r * width + c;
+                state.set(cell, state.domains[cell] & mask);
+            }
+            if state.domains.contains(&0) || !self.propagate(map, &mut state, (0..cells).collect()){
+                return None;
+            }
+            state.trail.clear();
+
+            let mut stack: Vec<WfcFrame> = vec![];
+            let mut backtracks = 0;
+            // the least certain hex is collapsed next
+            while let Some(&(_, _, cell)) = state.open.first(){
+                let choice = self.weighted_choice(state.domains[cell], &mut gen);
+                stack.push(WfcFrame { trail: state.trail.len(), cell, choice });
+                state.set(cell, choice);
+                if self.propagate(map, &mut state, vec![cell]){
+                    continue;
+                }
+
+                // undo choices until one can be ruled out without a contradiction
+                loop{
+                    let frame = stack.pop()?;
+                    backtracks += 1;
+                    if backtracks > self.max_backtracks{
+                        return None;
+                    }
+                    state.undo(frame.trail);
+                    let remaining = state.domains[frame.cell] & !frame.choice;
+                    state.set(frame.cell, remaining);
+                    if remaining != 0 && self.propagate(map, &mut state, vec![frame.cell]){
+                        break;
+                    }
+                }
+            }
+
+            Some(state.domains.iter().map(|d| self.rules.tiles[d.trailing_zeros() as usize].clone()).collect())
+        }
+
+        /// Remove tiles that no longer have a valid neighbor, returns false on a contradiction
+        fn propagate(&self, map: &Map, state: &mut WfcState, mut queue: Vec<usize>) -> bool{
+            let width = map.get_board_width();
+            while let Some(cell) = queue.pop(){
+                for d in HexDirection::ALL{
+                    let Some((r, c)) = map.neighbor_location(cell / width, cell % width, d) else { continue };
+                    let n = r * width + c;
+                    let allowed = (0..self.rules.tiles.len())
+                        .filter(|t| state.domains[cell] & (1 << t) != 0)
+                        .fold(0, |m, t| m | self.rules.adjacency[t][d.index()]);
+                    let reduced = state.domains[n] & allowed;
+                    if reduced != state.domains[n]{
+                        if reduced == 0{
+                            return false;
+                        }
+                        state.set(n, reduced);
+                        queue.push(n);
+                    }
+                }
+            }
+            true
+        }
+
+        /// Pick one tile from a domain by weight, or evenly if none of them have any weight
+        fn weighted_choice<R: Rng>(&self, domain: u64, gen: &mut R) -> u64{
+            let options: Vec<usize> = (0..self.rules.tiles.len()).filter(|t| domain & (1 << t) != 0).collect();
+            let total: f64 = options.iter().map(|t| self.rules.weights[*t].max(0.0)).sum();
+            if total <= 0.0{
+                return 1 << options[random_index(gen, options.len())];
+            }
+            let mut roll = gen.gen::<f64>() * total;
+            for t in options.iter(){
+                roll -= self.rules.weights[*t].max(0.0);
+                if roll < 0.0{
+                    return 1 << t;
+                }
+            }
+            1 << options[options.len() - 1]
+        }
+    }
+
+}
+
+
+pub mod render{
+
+    use crate::battle_map::{HexDirection, Map};
+    use crate::map_tiles::{BattleMapTileType, TileOwner};
+    use std::fs::File;
+    use std::io::{self, BufWriter, Write};
+    use std::path::Path;
+
+    /// Default distance from a hex's centre to its corners, in pixels
+    pub const DEFAULT_HEX_SIZE: u32 = 32;
+
+    const MARGIN: u32 = 8;
+    const OWNER_TINT: f64 = 0.3;
+    const OUTLINE: [u8; 3] = [40, 40, 40];
+    const BACKGROUND: [u8; 3] = [255, 255, 255];
+    const TEXT: [u8; 3] = [0, 0, 0];
+
+    pub const TILE_TYPES: [BattleMapTileType; 10] = [
+        BattleMapTileType::Forest, BattleMapTileType::Hill, BattleMapTileType::Mountain,
+        BattleMapTileType::Outpost, BattleMapTileType::Plains, BattleMapTileType::River,
+        BattleMapTileType::Road, BattleMapTileType::Swamp, BattleMapTileType::Town,
+        BattleMapTileType::Default,
+    ];
+
+    pub const TILE_OWNERS: [TileOwner; 5] = [
+        TileOwner::Attacker, TileOwner::Defender, TileOwner::LeftFlank,
+        TileOwner::RightFlank, TileOwner::SplitAttDef,
+    ];
+
+    /// Fill colour of a tile type
+    pub fn tile_colour(t_type: &BattleMapTileType) -> [u8; 3]{
+        match t_type{
+            BattleMapTileType::Forest => [34, 100, 34],
+            BattleMapTileType::Hill => [189, 166, 107],
+            BattleMapTileType::Mountain => [120, 105, 95],
+            BattleMapTileType::Outpost => [140, 40, 40],
+            BattleMapTileType::Plains => [150, 200, 110],
+            BattleMapTileType::River => [60, 120, 210],
+            BattleMapTileType::Road => [200, 170, 120],
+            BattleMapTileType::Swamp => [95, 110, 60],
+            BattleMapTileType::Town => [180, 90, 60],
+            BattleMapTileType::Default => [240, 240, 240],
+        }
+    }
+
+    /// Tint colour of an owner's region, matching the console colours
+    pub fn owner_colour(owner: &TileOwner) -> [u8; 3]{
+        match owner{
+            TileOwner::Defender => [0, 200, 220],
+            TileOwner::Attacker => [220, 30, 30],
+            TileOwner::LeftFlank => [30, 200, 30],
+            TileOwner::RightFlank => [160, 40, 200],
+            TileOwner::SplitAttDef => [230, 210, 30],
+        }
+    }
+
+    /// Name of a tile type as drawn in the legend
+    pub fn tile_name(t_type: &BattleMapTileType) -> &'static str{
+        match t_type{
+            BattleMapTileType::Forest => "Forest",
+            BattleMapTileType::Hill => "Hill",
+            BattleMapTileType::Mountain => "Mountain",
+            BattleMapTileType::Outpost => "Outpost",
+            BattleMapTileType::Plains => "Plains",
+            BattleMapTileType::River => "River",
+            BattleMapTileType::Road => "Road",
+            BattleMapTileType::Swamp => "Swamp",
+            BattleMapTileType::Town => "Town",
+            BattleMapTileType::Default => "Default",
+        }
+    }
+
+    /// Name of an owner as drawn in the legend
+    pub fn owner_name(owner: &TileOwner) -> &'static str{
+        match owner{
+            TileOwner::Defender => "Defender",
+            TileOwner::Attacker => "Attacker",
+            TileOwner::LeftFlank => "Left Flank",
+            TileOwner::RightFlank => "Right Flank",
+            TileOwner::SplitAttDef => "Split",
+        }
+    }
+
+    /// Pixel layout of the board, pointy topped hexes with odd rows shifted half a hex right
+    /// to match the neighbors given by `Map::get_neighbors`
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct HexLayout{
+        size: f64,
+        origin: (f64, f64),
+    }
+
+    impl HexLayout{
+        /// Layout for hexes of `size` pixels from centre to corner, with the board's top left at `origin`
+        pub fn new(size: f64, origin: (f64, f64)) -> Self{
+            HexLayout { size, origin }
+        }
+
+        pub fn hex_width(&self) -> f64{
+            3f64.sqrt() * self.size
+        }
+
+        pub fn hex_height(&self) -> f64{
+            2.0 * self.size
+        }
+
+        /// Pixel centre of the hex at (row, column)
+        pub fn center(&self, row: usize, column: usize) -> (f64, f64){
+            let shift = match row % 2 { 0 => 0.5, _ => 1.0 };
+            (self.origin.0 + self.hex_width() * (column as f64 + shift),
+             self.origin.1 + self.size + 1.5 * self.size * row as f64)
+        }
+
+        /// The six corners of a hex, clockwise from the top
+        pub fn corners(&self, row: usize, column: usize) -> [(f64, f64); 6]{
+            let (cx, cy) = self.center(row, column);
+            let half_width = self.hex_width() / 2.0;
+            let half = self.size / 2.0;
+            [(cx, cy - self.size), (cx + half_width, cy - half), (cx + half_width, cy + half),
+             (cx, cy + self.size), (cx - half_width, cy + half), (cx - half_width, cy - half)]
+        }
+
+        /// Point `distance` pixels from a hex centre in the given direction,
+        /// a hex width reaches the neighbor's centre and half of one the shared edge
+        pub fn toward(&self, row: usize, column: usize, direction: HexDirection, distance: f64) -> (f64, f64){
+            let (cx, cy) = self.center(row, column);
+            let angle = (direction.index() as f64 * 60.0).to_radians();
+            (cx + distance * angle.cos(), cy + distance * angle.sin())
+        }
+
+        /// Pixel size of a whole board
+        pub fn board_size(&self, board_width: usize, board_height: usize) -> (f64, f64){
+            let odd_shift = match board_height > 1 { true => 0.5, false => 0.0 };
+            (self.hex_width() * (board_width as f64 + odd_shift),
+             self.size * (1.5 * board_height as f64 + 0.5))
+        }
+
+        /// Whether a point lies within `size` of a hex centre, measured as a pointy topped hex
+        fn contains(center: (f64, f64), size: f64, point: (f64, f64)) -> bool{
+            let dx = (point.0 - center.0).abs();
+            let dy = (point.1 - center.1).abs();
+            dx <= 3f64.sqrt() / 2.0 * size && dy <= size - dx / 3f64.sqrt()
+        }
+    }
+
+    /// An RGB image drawn by the renderer
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct Canvas{
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    }
+
+    impl Canvas{
+        pub fn new(width: u32, height: u32) -> Self{
+            Canvas { width, height, pixels: BACKGROUND.repeat((width * height) as usize) }
+        }
+
+        pub fn get_width(&self) -> u32{
+            self.width
+        }
+
+        pub fn get_height(&self) -> u32{
+            self.height
+        }
+
+        /// Colour at a pixel, None if it is outside the image
+        pub fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 3]>{
+            if x >= self.width || y >= self.height{
+                return None;
+            }
+            let i = ((y * self.width + x) * 3) as usize;
+            Some([self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]])
+        }
+
+        pub fn set_pixel(&mut self, x: u32, y: u32, colour: [u8; 3]){
+            if x < self.width && y < self.height{
+                let i = ((y * self.width + x) * 3) as usize;
+                self.pixels[i..i + 3].copy_from_slice(&colour);
+            }
+        }
+
+        pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, colour: [u8; 3]){
+            for py in y..y + height{
+                for px in x..x + width{
+                    self.set_pixel(px, py, colour);
+                }
+            }
+        }
+
+        /// Raw RGB bytes, row by row
+        pub fn get_pixels(&self) -> &[u8]{
+            &self.pixels
+        }
+
+        /// Encode the image as a PNG
+        pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()>{
+            let mut encoder = png::Encoder::new(writer, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.pixels)?;
+            Ok(())
+        }
+    }
+
+    /// Draws battle maps to PNG images
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct PngRenderer{
+        hex_size: u32,
+        show_owners: bool,
+        show_coordinates: bool,
+        show_legend: bool,
+    }
+
+    impl Default for PngRenderer{
+        fn default() -> Self{
+            PngRenderer::new(DEFAULT_HEX_SIZE)
+        }
+    }
+
+    impl PngRenderer{
+        /// A renderer drawing hexes `hex_size` pixels from centre to corner, with coordinates and a legend
+        pub fn new(hex_size: u32) -> Self{
+            PngRenderer { hex_size: hex_size.max(4), show_owners: false, show_coordinates: true, show_legend: true }
+        }
+
+        /// Tint each hex with the colour of the region that owns it
+        pub fn set_show_owners(&mut self, show_owners: bool){
+            self.show_owners = show_owners;
+        }
+
+        /// Write "row,column" in each hex, skipped when hexes are too small to fit it
+        pub fn set_show_coordinates(&mut self, show_coordinates: bool){
+            self.show_coordinates = show_coordinates;
+        }
+
+        /// Draw a key of tile colours, and owner colours when tinting, below the board
+        pub fn set_show_legend(&mut self, show_legend: bool){
+            self.show_legend = show_legend;
+        }
+
+        pub fn get_hex_size(&self) -> u32{
+            self.hex_size
+        }
+
+        /// Layout of the board within the rendered image
+        pub fn layout(&self) -> HexLayout{
+            HexLayout::new(self.hex_size as f64, (MARGIN as f64, MARGIN as f64))
+        }
+
+        /// Draw the map to an image
+        pub fn render(&self, map: &Map) -> Canvas{
+            let layout = self.layout();
+            let (board_w, board_h) = layout.board_size(map.get_board_width(), map.get_board_height());
+            let scale = self.text_scale();
+            let legend = self.legend_entries();
+            let line_height = (GLYPH_HEIGHT + 3) * scale;
+            let legend_h = match self.show_legend{
+                true => MARGIN + legend.len() as u32 * line_height,
+                false => 0,
+            };
+            let legend_w = legend.iter().map(|(name, _)| line_height + text_width(name, scale)).max().unwrap_or(0);
+            let width = (board_w.ceil() as u32).max(legend_w) + 2 * MARGIN;
+            let height = board_h.ceil() as u32 + 2 * MARGIN + legend_h;
+            let mut canvas = Canvas::new(width, height);
+
+            for row in 0..map.get_board_height(){
+                for column in 0..map.get_board_width(){
+                    let tile = map.get_tile(row, column).expect("Tile is on the board");
+                    let mut colour = tile_colour(tile.get_type());
+                    if self.show_owners{
+                        colour = blend(colour, owner_colour(tile.get_owner()), OWNER_TINT);
+                    }
+                    self.draw_hex(&mut canvas, layout.center(row, column), colour);
+                    if self.show_coordinates{
+                        let label = format!("{},{}", row, column);
+                        if (text_width(&label, scale) as f64) < layout.hex_width() - 4.0{
+                            let (cx, cy) = layout.center(row, column);
+                            let x = cx - text_width(&label, scale) as f64 / 2.0;
+                            let y = cy + self.hex_size as f64 / 2.0 - (GLYPH_HEIGHT * scale) as f64 - 1.0;
+                            draw_text(&mut canvas, x.max(0.0) as u32, y.max(0.0) as u32, &label, scale, TEXT);
+                        }
+                    }
+                }
+            }
+
+            if self.show_legend{
+                let mut y = board_h.ceil() as u32 + 2 * MARGIN;
+                for (name, colour) in legend{
+                    let swatch = GLYPH_HEIGHT * scale;
+                    canvas.fill_rect(MARGIN, y, swatch, swatch, colour);
+                    draw_text(&mut canvas, MARGIN + swatch + 2 * scale, y, name, scale, TEXT);
+                    y += line_height;
+                }
+            }
+            canvas
+        }
+
+        /// Draw the map and save it as a PNG at `path`
+        pub fn save<P: AsRef<Path>>(&self, map: &Map, path: P) -> io::Result<()>{
+            let file = BufWriter::new(File::create(path)?);
+            self.render(map).write_png(file)
+        }
+
+        fn draw_hex(&self, canvas: &mut Canvas, center: (f64, f64), colour: [u8; 3]){
+            let size = self.hex_size as f64;
+            let half_width = 3f64.sqrt() / 2.0 * size;
+            let x0 = (center.0 - half_width).floor().max(0.0) as u32;
+            let y0 = (center.1 - size).floor().max(0.0) as u32;
+            for y in y0..=(center.1 + size).ceil() as u32{
+                for x in x0..=(center.0 + half_width).ceil() as u32{
+                    let p = (x as f64 + 0.5, y as f64 + 0.5);
+                    if HexLayout::contains(center, size, p){
+                        match HexLayout::contains(center, size - 1.5, p){
+                            true => canvas.set_pixel(x, y, colour),
+                            false => canvas.set_pixel(x, y, OUTLINE),
+                        }
+                    }
+                }
+            }
+        }
+
+        fn text_scale(&self) -> u32{
+            (self.hex_size / 16).max(1)
+        }
+
+        fn legend_entries(&self) -> Vec<(&'static str, [u8; 3])>{
+            let mut entries: Vec<(&'static str, [u8; 3])> = TILE_TYPES.iter().map(|t| (tile_name(t), tile_colour(t))).collect();
+            if self.show_owners{
+                entries.extend(TILE_OWNERS.iter().map(|o| (owner_name(o), owner_colour(o))));
+            }
+            entries
+        }
+    }
+
+    fn blend(base: [u8; 3], tint: [u8; 3], amount: f64) -> [u8; 3]{
+        let mix = |a: u8, b: u8| (a as f64 * (1.0 - amount) + b as f64 * amount).round() as u8;
+        [mix(base[0], tint[0]), mix(base[1], tint[1]), mix(base[2], tint[2])]
+    }
+
+    const GLYPH_WIDTH: u32 = 3;
+    const GLYPH_HEIGHT: u32 = 5;
+
+    /// 3x5 bitmap font, each row's bits read left to right from 0b100
+    fn glyph(c: char) -> [u8; 5]{
+        match c.to_ascii_uppercase(){
+            '0' => [7, 5, 5, 5, 7], '1' => [2, 6, 2, 2, 7], '2' => [7, 1, 7, 4, 7], '3' => [7, 1, 7, 1, 7],
+            '4' => [5, 5, 7, 1, 1], '5' => [7, 4, 7, 1, 7], '6' => [7, 4, 7, 5, 7], '7' => [7, 1, 1, 1, 1],
+            '8' => [7, 5, 7, 5, 7], '9' => [7, 5, 7, 1, 7], ',' => [0, 0, 0, 2, 4], '-' => [0, 0, 7, 0, 0],
+            'A' => [2, 5, 7, 5, 5], 'B' => [6, 5, 6, 5, 6], 'C' => [3, 4, 4, 4, 3], 'D' => [6, 5, 5, 5, 6],
+            'E' => [7, 4, 6, 4, 7], 'F' => [7, 4, 6, 4, 4], 'G' => [3, 4, 5, 5, 3], 'H' => [5, 5, 7, 5, 5],
+            'I' => [7, 2, 2, 2, 7], 'J' => [1, 1, 1, 5, 2], 'K' => [5, 5, 6, 5, 5], 'L' => [4, 4, 4, 4, 7],
+            'M' => [5, 7, 7, 5, 5], 'N' => [6, 5, 5, 5, 5], 'O' => [2, 5, 5, 5, 2], 'P' => [6, 5, 6, 4, 4],
+            'Q' => [2, 5, 5, 6, 3], 'R' => [6, 5, 6, 5, 5], 'S' => [3, 4, 2, 1, 6], 'T' => [7, 2, 2, 2, 2],
+            'U' => [5, 5, 5, 5, 7], 'V' => [5, 5, 5, 5, 2], 'W' => [5, 5, 7, 7, 5], 'X' => [5, 5, 2, 5, 5],
+            'Y' => [5, 5, 2, 2, 2], 'Z' => [7, 1, 2, 4, 7],
+            _ => [0; 5],
+        }
+    }
+
+    fn text_width(text: &str, scale: u32) -> u32{
+        (text.chars().count() as u32 * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale
+    }
+
+    fn draw_text(canvas: &mut Canvas, x: u32, y: u32, text: &str, scale: u32, colour: [u8; 3]){
+        for (i, c) in text.chars().enumerate(){
+            let gx = x + i as u32 * (GLYPH_WIDTH + 1) * scale;
+            for (row, bits) in glyph(c).iter().enumerate(){
+                for col in 0..GLYPH_WIDTH{
+                    if bits & (0b100 >> col) != 0{
+                        canvas.fill_rect(gx + col * scale, y + row as u32 * scale, scale, scale, colour);
+                    }
+                }
+            }
+        }
+    }
+
+}
+
+
+pub mod svg{
+
+    use crate::battle_map::{HexDirection, Map};
+    use crate::hex_paths::HexNetwork;
+    use crate::map_tiles::{BattleMapTileType, MapAddOn, RiverCrossing, TileOwner};
+    use crate::render::{self, HexLayout, DEFAULT_HEX_SIZE, TILE_TYPES};
+    use std::collections::BTreeSet;
+    use std::fmt::Write as _;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    const MARGIN: f64 = 8.0;
+
+    /// CSS class of a tile type, e.g. "tile-forest"
+    pub fn tile_class(t_type: &BattleMapTileType) -> String{
+        format!("tile-{}", render::tile_name(t_type).to_lowercase())
+    }
+
+    /// CSS class of an owner, e.g. "owner-left-flank"
+    pub fn owner_class(owner: &TileOwner) -> &'static str{
+        match owner{
+            TileOwner::Defender => "owner-defender",
+            TileOwner::Attacker => "owner-attacker",
+            TileOwner::LeftFlank => "owner-left-flank",
+            TileOwner::RightFlank => "owner-right-flank",
+            TileOwner::SplitAttDef => "owner-split-att-def",
+        }
+    }
+
+    /// CSS class of a river crossing, e.g. "crossing-ford"
+    pub fn crossing_class(crossing: &RiverCrossing) -> &'static str{
+        match crossing{
+            RiverCrossing::Ford => "crossing-ford",
+            RiverCrossing::BridgeStone => "crossing-bridge-stone",
+            RiverCrossing::BridgeWood => "crossing-bridge-wood",
+        }
+    }
+
+    /// CSS class of a fortification, e.g. "add-on-barricade"
+    pub fn add_on_class(add_on: &MapAddOn) -> &'static str{
+        match add_on{
+            MapAddOn::Barricade => "add-on-barricade",
+            MapAddOn::Palisade => "add-on-palisade",
+            MapAddOn::Trench => "add-on-trench",
+            MapAddOn::Wall => "add-on-wall",
+        }
+    }
+
+    /// Exports battle maps as SVG, with classes on every element so they can be restyled with CSS
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct SvgRenderer{
+        hex_size: u32,
+        embed_style: bool,
+    }
+
+    impl Default for SvgRenderer{
+        fn default() -> Self{
+            SvgRenderer::new(DEFAULT_HEX_SIZE)
+        }
+    }
+
+    impl SvgRenderer{
+        /// A renderer drawing hexes `hex_size` units from centre to corner, with a default stylesheet
+        pub fn new(hex_size: u32) -> Self{
+            SvgRenderer { hex_size: hex_size.max(1), embed_style: true }
+        }
+
+        /// Embed the default stylesheet, turn off to style the map entirely from outside
+        pub fn set_embed_style(&mut self, embed_style: bool){
+            self.embed_style = embed_style;
+        }
+
+        pub fn layout(&self) -> HexLayout{
+            HexLayout::new(self.hex_size as f64, (MARGIN, MARGIN))
+        }
+
+        /// Draw the map as an SVG document
+        /// Each hex is a polygon classed by tile type, owner and any crossing or fortification, rivers and roads are
+        /// polylines through the hex centres, and the seed, size, campaign inputs, attack direction and
+        /// deployment layout are kept as data attributes on the root element
+        pub fn render(&self, map: &Map) -> String{
+            let layout = self.layout();
+            let (w, h) = layout.board_size(map.get_board_width(), map.get_board_height());
+            let (w, h) = (w + 2.0 * MARGIN, h + 2.0 * MARGIN);
+            let tiles = map.get_campaign_tiles();
+
+            let mut out = String::new();
+            let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            let _ = writeln!(out, concat!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.2} {:.2}" "#,
+                r#"data-seed="{}" data-width="{}" data-height="{}" data-attacker="{}" data-defender="{}" "#,
+                r#"data-left-flank="{}" data-right-flank="{}" data-cities="{}" data-rivers="{}" "#,
+                r#"data-mountains="{}" data-hills="{}" data-search-radius="{}" data-attack-direction="{}" data-layout="{}">"#),
+                w.ceil(), h.ceil(), w, h,
+                map.get_seed(), map.get_board_width(), map.get_board_height(),
+                tiles.get_attacker(), tiles.get_defender(), tiles.get_left_flank(), tiles.get_right_flank(),
+                tiles.get_cities_within_search_radius(), tiles.get_rivers_within_search_radius(),
+                tiles.get_mountains_within_search_radius(), tiles.get_hills_within_search_radius(),
+                tiles.get_search_radius(), tiles.get_attack_direction(), map.get_layout());
+            let _ = writeln!(out, "  <title>Battle map {}w x {}h, seed {}</title>", map.get_board_width(), map.get_board_height(), map.get_seed());
+            if self.embed_style{
+                let _ = writeln!(out, "  <style>\n{}  </style>", default_style());
+            }
+
+            let _ = writeln!(out, r#"  <g class="hexes">"#);
+            for row in 0..map.get_board_height(){
+                for column in 0..map.get_board_width(){
+                    let tile = map.get_tile(row, column).expect("Tile is on the board");
+                    let mut class = format!("hex {} {}", tile_class(tile.get_type()), owner_class(tile.get_owner()));
+                    if let Some(c) = tile.get_crossing(){
+                        class.push(' ');
+                        class.push_str(crossing_class(c));
+                    }
+                    if let Some(a) = tile.get_add_on(){
+                        class.push(' ');
+                        class.push_str(add_on_class(a));
+                    }
+                    let points: Vec<String> = layout.corners(row, column).iter().map(|(x, y)| format!("{:.2},{:.2}", x, y)).collect();
+                    let _ = writeln!(out, r#"    <polygon class="{}" data-row="{}" data-column="{}" points="{}"/>"#,
+                        class, row, column, points.join(" "));
+                }
+            }
+            let _ = writeln!(out, "  </g>");
+
+            for (name, network) in [("rivers", map.get_rivers()), ("roads", map.get_roads())]{
+                let _ = writeln!(out, r#"  <g class="{}">"#, name);
+                for line in polylines(map, network, &layout){
+                    let points: Vec<String> = line.iter().map(|(x, y)| format!("{:.2},{:.2}", x, y)).collect();
+                    let _ = writeln!(out, r#"    <polyline class="{}" points="{}"/>"#, name.trim_end_matches('s'), points.join(" "));
+                }
+                let _ = writeln!(out, "  </g>");
+            }
+            let _ = writeln!(out, "</svg>");
+            out
+        }
+
+        /// Draw the map and save it as an SVG at `path`
+        pub fn save<P: AsRef<Path>>(&self, map: &Map, path: P) -> io::Result<()>{
+            fs::write(path, self.render(map))
+        }
+    }
+
+    /// Stylesheet giving every tile type, owner and network its render colour
+    pub fn default_style() -> String{
+        let hex = |c: [u8; 3]| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+        let mut style = String::from("    .hex { stroke: #282828; stroke-width: 1; }\n");
+        for t in TILE_TYPES.iter(){
+            let _ = writeln!(style, "    .{} {{ fill: {}; }}", tile_class(t), hex(render::tile_colour(t)));
+        }
+        style.push_str("    .river { fill: none; stroke: #1e50a0; stroke-width: 3; stroke-linecap: round; }\n");
+        style.push_str("    .road { fill: none; stroke: #6e4b28; stroke-width: 2; stroke-dasharray: 4 2; stroke-linecap: round; }\n");
+        style
+    }
+
+    /// Break a network into polylines through hex centres, each running between junctions,
+    /// dead ends or exits off the board, which end halfway to the missing neighbor
+    fn polylines(map: &Map, network: &HexNetwork, layout: &HexLayout) -> Vec<Vec<(f64, f64)>>{
+        let is_break = |l: (usize, usize)| network.get_connections(l).len() != 2;
+        let mut visited: BTreeSet<((usize, usize), HexDirection)> = BTreeSet::new();
+        let mut lines = vec![];
+
+        // start from junctions and ends, then pick up any loops left over
+        let mut starts: Vec<(usize, usize)> = network.locations().into_iter().filter(|l| is_break(*l)).collect();
+        starts.extend(network.locations());
+        let is_exit = |l: (usize, usize), d: HexDirection| map.neighbor_location(l.0, l.1, d).is_none();
+        for start in starts{
+            // walk onto the board first so a hex with an exit starts its line at the board edge
+            let mut directions = network.get_connections(start).to_vec();
+            directions.sort_by_key(|d| is_exit(start, *d));
+            for d in directions{
+                if visited.contains(&(start, d)){
+                    continue;
+                }
+                let mut line = vec![layout.center(start.0, start.1)];
+                if !is_break(start){
+                    let back = network.get_connections(start).iter().copied().find(|c| *c != d);
+                    if let Some(b) = back.filter(|b| is_exit(start, *b) && !visited.contains(&(start, *b))){
+                        visited.insert((start, b));
+                        line.insert(0, layout.toward(start.0, start.1, b, layout.hex_width() / 2.0));
+                    }
+                }
+                let (mut current, mut direction) = (start, d);
+                loop{
+                    visited.insert((current, direction));
+                    let Some(next) = map.neighbor_location(current.0, current.1, direction) else {
+                        line.push(layout.toward(current.0, current.1, direction, layout.hex_width() / 2.0));
+                        break;
+                    };
+                    visited.insert((next, direction.opposite()));
+                    line.push(layout.center(next.0, next.1));
+                    if is_break(next) || next == start{
+                        break;
+                    }
+                    let onward = network.get_connections(next).iter().copied().find(|c| *c != direction.opposite());
+                    match onward{
+                        Some(o) if !visited.contains(&(next, o)) => {
+                            current = next;
+                            direction = o;
+                        },
+                        _ => break,
+                    }
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+}
+
+
+pub mod campaign{
+
+    use crate::battle_map::HexDirection;
+    use crate::coords::HexCoord;
+    use crate::errors::MapGenError;
+    use crate::map_tiles::{CampaignGenerationTiles, CampaignMapTile, CampaignMapTileType};
+    use std::path::Path;
+
+    /// The campaign board, a hex grid of campaign tiles laid out like the battle map with odd rows shifted right
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(feature = "serde", serde(try_from = "CampaignMapData"))]
+    pub struct CampaignMap{
+        width: usize,
+        height: usize,
+        tiles: Vec<CampaignMapTile>,
+        search_radius: u32,
+    }
+
+    /// A campaign as written to JSON, checked by `from_tiles` before it becomes a `CampaignMap`
+    #[cfg(feature = "serde")]
+    #[derive(serde::Deserialize)]
+    struct CampaignMapData{
+        width: usize,
+        height: usize,
+        tiles: Vec<CampaignMapTile>,
+        search_radius: u32,
+    }
+
+    #[cfg(feature = "serde")]
+    impl TryFrom<CampaignMapData> for CampaignMap{
+        type Error = MapGenError;
+
+        fn try_from(data: CampaignMapData) -> Result<Self, Self::Error>{
+            let mut campaign = CampaignMap::from_tiles(data.width, data.height, data.tiles)?;
+            campaign.set_search_radius(data.search_radius);
+            Ok(campaign)
+        }
+    }
+
+    impl CampaignMap{
+        /// A board of default tiles, searching 1 hex around battles
+        pub fn new(width: usize, height: usize) -> Result<Self, MapGenError>{
+            CampaignMap::from_tiles(width, height, vec![CampaignMapTile::default(); width * height])
+        }
+
+        /// A board from its tiles in row-major order
+        pub fn from_tiles(width: usize, height: usize, tiles: Vec<CampaignMapTile>) -> Result<Self, MapGenError>{
+            if width == 0 || height == 0{
+                return Err(MapGenError::BoardTooSmall { width, height });
+            }
+            if tiles.len() != width * height{
+                return Err(MapGenError::TileCountMismatch { expected: width * height, found: tiles.len() });
+            }
+            Ok(CampaignMap { width, height, tiles, search_radius: 1 })
+        }
+
+        pub fn get_width(&self) -> usize{
+            self.width
+        }
+
+        pub fn get_height(&self) -> usize{
+            self.height
+        }
+
+        /// Hexes around a battle counted towards its city, river, mountain and hill densities
+        pub fn set_search_radius(&mut self, search_radius: u32){
+            self.search_radius = search_radius;
+        }
+
+        pub fn get_search_radius(&self) -> u32{
+            self.search_radius
+        }
+
+        pub fn get_tile(&self, row: usize, column: usize) -> Option<&CampaignMapTile>{
+            match row < self.height && column < self.width{
+                true => self.tiles.get(row * self.width + column),
+                false => None,
+            }
+        }
+
+        pub fn set_tile(&mut self, row: usize, column: usize, tile: CampaignMapTile) -> Result<(), MapGenError>{
+            match row < self.height && column < self.width{
+                true => {
+                    self.tiles[row * self.width + column] = tile;
+                    Ok(())
+                },
+                false => Err(self.out_of_bounds((row, column))),
+            }
+        }
+
+        /// Tile at a hex, None if it is off the board
+        pub fn get_tile_at(&self, coord: HexCoord) -> Option<&CampaignMapTile>{
+            coord.to_offset().and_then(|(r, c)| self.get_tile(r, c))
+        }
+
+        /// Generation inputs for a battle where the attacker moves into the neighboring defender's hex,
+        /// attacking from the direction of its hex so the battle map keeps the campaign's bearings
+        /// The flanks are the two hexes next to both, left and right as the defender faces the attacker,
+        /// and a default tile where that hex is off the board
+        /// Features are counted within the search radius of the defender's hex
+        pub fn battle_inputs(&self, attacker: (usize, usize), defender: (usize, usize)) -> Result<CampaignGenerationTiles, MapGenError>{
+            let attacker_tile = self.get_tile(attacker.0, attacker.1).ok_or_else(|| self.out_of_bounds(attacker))?;
+            let defender_tile = self.get_tile(defender.0, defender.1).ok_or_else(|| self.out_of_bounds(defender))?;
+            let (a, d) = (HexCoord::from(attacker), HexCoord::from(defender));
+            let facing = HexDirection::ALL.iter()
+                .position(|dir| d.neighbor(*dir) == a)
+                .ok_or(MapGenError::NotAdjacent { attacker, defender })?;
+            let flank = |steps: usize| self.get_tile_at(d.neighbor(HexDirection::ALL[(facing + steps) % 6])).cloned().unwrap_or_default();
+
+            let nearby: Vec<&CampaignMapTile> = self.tiles.iter().enumerate()
+                .filter(|(i, _)| HexCoord::from_offset(i / self.width, i % self.width).distance(d) <= self.search_radius)
+                .map(|(_, t)| t)
+                .collect();
+            let count = |f: &dyn Fn(&CampaignMapTile) -> bool| nearby.iter().filter(|t| f(t)).count() as u32;
+            let mut inputs = CampaignGenerationTiles::new(
+                attacker_tile.clone(),
+                defender_tile.clone(),
+                flank(5),
+                flank(1),
+                count(&|t| t.get_type() == &CampaignMapTileType::Town),
+                count(&|t| t.has_river()),
+                count(&|t| t.get_type() == &CampaignMapTileType::Mountain),
+                count(&|t| t.get_type() == &CampaignMapTileType::Hill),
+                self.search_radius,
+            );
+            inputs.set_attack_direction(HexDirection::ALL[facing]);
+            Ok(inputs)
+        }
+
+        fn out_of_bounds(&self, location: (usize, usize)) -> MapGenError{
+            MapGenError::OutOfBounds { row: location.0, column: location.1, width: self.width, height: self.height }
+        }
+
+        /// Read a campaign from text, one board row per line with tiles separated by whitespace,
+        /// e.g. "Plains Forest+Road Town", rows offset like the battle map
+        /// Blank lines and anything after '#' are skipped, and a "search_radius = N" line sets the search radius
+        pub fn from_text(text: &str) -> Result<Self, MapGenError>{
+            let mut rows: Vec<Vec<CampaignMapTile>> = vec![];
+            let mut search_radius = None;
+            for (i, line) in text.lines().enumerate(){
+                let line_error = |message: String| MapGenError::CampaignFile { line: i + 1, message };
+                let line = line.split('#').next().unwrap_or("").trim();
+                if line.is_empty(){
+                    continue;
+                }
+                if let Some((key, value)) = line.split_once('='){
+                    match key.trim(){
+                        "search_radius" => search_radius = Some(value.trim().parse()
+                            .map_err(|_| line_error(format!("Search radius must be a whole number, found {}", value.trim())))?),
+                        k => return Err(line_error(format!("Unknown setting: {}", k))),
+                    }
+                    continue;
+                }
+                let row = line.split_whitespace()
+                    .map(|t| t.parse().map_err(|e: MapGenError| line_error(e.to_string())))
+                    .collect::<Result<Vec<CampaignMapTile>, _>>()?;
+                if let Some(first) = rows.first().filter(|f| f.len() != row.len()){
+                    return Err(line_error(format!("Row has {} tiles, expected {}", row.len(), first.len())));
+                }
+                rows.push(row);
+            }
+            CampaignMap::from_rows(rows, search_radius)
+        }
+
+        /// Read a campaign from JSON, e.g. {"search_radius": 2, "rows": [["Plains", "Forest+Road"], ["Town", "Hill"]]}
+        #[cfg(feature = "serde")]
+        pub fn from_json(json: &str) -> Result<Self, MapGenError>{
+            let doc: CampaignDocument = serde_json::from_str(json).map_err(|e| {
+                let location = format!(" at line {} column {}", e.line(), e.column());
+                MapGenError::CampaignFile { line: e.line(), message: e.to_string().trim_end_matches(&location).to_string() }
+            })?;
+            CampaignMap::from_rows(doc.rows.0, doc.search_radius)
+        }
+
+        /// Read a campaign from TOML, e.g. search_radius = 2 and rows = [["Plains", "Forest+Road"], ["Town", "Hill"]]
+        #[cfg(feature = "toml")]
+        pub fn from_toml(text: &str) -> Result<Self, MapGenError>{
+            let doc: CampaignDocument = toml::from_str(text).map_err(|e| MapGenError::CampaignFile {
+                line: e.span().map_or(0, |s| text[..s.start].matches('\n').count() + 1),
+                message: e.message().to_string(),
+            })?;
+            CampaignMap::from_rows(doc.rows.0, doc.search_radius)
+        }
+
+        fn from_rows(rows: Vec<Vec<CampaignMapTile>>, search_radius: Option<u32>) -> Result<Self, MapGenError>{
+            let (height, width) = (rows.len(), rows.first().map_or(0, |r| r.len()));
+            let mut campaign = CampaignMap::from_tiles(width, height, rows.into_iter().flatten().collect())?;
+            campaign.set_search_radius(search_radius.unwrap_or(1));
+            Ok(campaign)
+        }
+    }
+
+    /// Load a campaign file, reading .json and .toml files in those formats and anything else as text
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CampaignMap, MapGenError>{
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(){
+            #[cfg(feature = "serde")]
+            Some("json") => CampaignMap::from_json(&text),
+            #[cfg(not(feature = "serde"))]
+            Some("json") => Err(MapGenError::Parse("Reading JSON campaigns needs the serde feature".to_string())),
+            #[cfg(feature = "toml")]
+            Some("toml") => CampaignMap::from_toml(&text),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(MapGenError::Parse("Reading TOML campaigns needs the toml feature".to_string())),
+            _ => CampaignMap::from_text(&text),
+        }
+    }
+
+    /// Campaign file layout shared by JSON and TOML
+    #[cfg(feature = "serde")]
+    #[derive(serde::Deserialize)]
+    struct CampaignDocument{
+        #[serde(default)]
+        search_radius: Option<u32>,
+        rows: CampaignRows,
+    }
+
+    /// Board rows, parsing each tile as "Type" or "Type+AddOn" and checking every row is as long as the first
+    /// so errors are reported where the parser is
+    #[cfg(feature = "serde")]
+    struct CampaignRows(Vec<Vec<CampaignMapTile>>);
+
+    #[cfg(feature = "serde")]
+    struct TileName(CampaignMapTile);
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for TileName{
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>{
+            let name = String::deserialize(deserializer)?;
+            name.parse().map(TileName).map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for CampaignRows{
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>{
+            struct RowsVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for RowsVisitor{
+                type Value = CampaignRows;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+                    write!(f, "a list of rows of campaign tiles")
+                }
+
+                fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error>{
+                    let mut rows: Vec<Vec<CampaignMapTile>> = vec![];
+                    while let Some(row) = seq.next_element::<Vec<TileName>>()?{
+                        if let Some(first) = rows.first().filter(|f| f.len() != row.len()){
+                            return Err(serde::de::Error::custom(format!("Row has {} tiles, expected {}", row.len(), first.len())));
+                        }
+                        rows.push(row.into_iter().map(|t| t.0).collect());
+                    }
+                    Ok(CampaignRows(rows))
+                }
+            }
+
+            deserializer.deserialize_seq(RowsVisitor)
+        }
+    }
+
+}
+
+pub mod output{
+
+    use crate::battle_map::Map;
+    use crate::errors::MapGenError;
+    use crate::render::PngRenderer;
+    use crate::svg::SvgRenderer;
+    use std::fmt;
+    use std::fs;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    /// Formats a battle map can be written in
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum MapFormat{
+        /// Hex grid of tile types as text
+        Ascii,
+        /// Hex grid of tile owners as text
+        Owners,
+        #[cfg(feature = "serde")]
+        Json,
+        Png,
+        Svg,
+    }
+
+    impl MapFormat{
+        /// File extension for the format
+        pub fn extension(&self) -> &'static str{
+            match self{
+                MapFormat::Ascii | MapFormat::Owners => "txt",
+                #[cfg(feature = "serde")]
+                MapFormat::Json => "json",
+                MapFormat::Png => "png",
+                MapFormat::Svg => "svg",
+            }
+        }
+
+        /// The map in this format as text, None for image formats
+        pub fn to_text(&self, map: &Map, colored: bool) -> Option<String>{
+            match self{
+                MapFormat::Ascii => Some(map.board_tiles_string(colored)),
+                MapFormat::Owners => Some(map.board_owners_string(colored)),
+                #[cfg(feature = "serde")]
+                MapFormat::Json => Some(map.to_json()),
+                MapFormat::Png => None,
+                MapFormat::Svg => Some(SvgRenderer::default().render(map)),
+            }
+        }
+
+        /// Save the map in this format at `path`, text without terminal colours
+        pub fn save<P: AsRef<Path>>(&self, map: &Map, path: P) -> Result<(), MapGenError>{
+            match self.to_text(map, false){
+                Some(text) => fs::write(path, text)?,
+                None => PngRenderer::default().save(map, path)?,
+            }
+            Ok(())
+        }
+    }
+
+    impl fmt::Display for MapFormat{
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+            let name = match self{
+                MapFormat::Ascii => "ascii",
+                MapFormat::Owners => "owners",
+                #[cfg(feature = "serde")]
+                MapFormat::Json => "json",
+                MapFormat::Png => "png",
+                MapFormat::Svg => "svg",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    impl FromStr for MapFormat{
+        type Err = MapGenError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err>{
+            match s.trim().to_lowercase().as_str(){
+                "ascii" | "txt" => Ok(MapFormat::Ascii),
+                "owners" => Ok(MapFormat::Owners),
+                #[cfg(feature = "serde")]
+                "json" => Ok(MapFormat::Json),
+                #[cfg(not(feature = "serde"))]
+                "json" => Err(MapGenError::Parse(String::from("JSON output needs the serde feature"))),
+                "png" => Ok(MapFormat::Png),
+                "svg" => Ok(MapFormat::Svg),
+                _ => Err(MapGenError::Parse(format!("Unknown map format: {}", s))),
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::{campaign::{self, CampaignMap}, clusters, coords::HexCoord, deployment::DeploymentLayout, errors::MapGenError, elevation, fortifications, output::MapFormat, outposts, towns, pathfinding::{self, MovementCosts}, visibility::SightRules, battle_map::{BoardEdge, HexDirection, Map, MapGenerator}, hex_paths::HexNetwork, rivers::{self, RiverTileType}, roads::{self, RoadTileType}, wfc::{self, WfcRules, WfcSolver}, render::{self, PngRenderer}, svg::SvgRenderer, map_tiles::{BattleMapTileType, CampaignGenerationTiles, CampaignMapTile, CampaignMapTileAddOn, CampaignMapTileType, MapAddOn, MapTile, RiverCrossing, TileOwner}};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn map_print(){
+        for i in 4..=10{
+            for j in 2..=10{
+                let m = MapGenerator::default().create_map(i,j);
+                m.print_board_tiles();
+            }
+        }
+
+        // attacks from the side need a row for each flank and one between them
+        for direction in [HexDirection::Left, HexDirection::Right]{
+            let mut tiles = CampaignGenerationTiles::default();
+            tiles.set_attack_direction(direction);
+            let g = MapGenerator::new(tiles);
+            for i in 4..=10{
+                for j in 3..=10{
+                    g.create_map(i,j).print_board_owners();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn get_neighbors(){
+        // Arrange
+        let mut m = MapGenerator::default().create_map(4, 3);
+        m.set_tile(0, 0, MapTile::new(BattleMapTileType::Plains));
+        m.set_tile(0, 1, MapTile::new(BattleMapTileType::Forest));
+        m.set_tile(0, 2, MapTile::new(BattleMapTileType::Hill));
+        m.set_tile(1, 0, MapTile::new(BattleMapTileType::Mountain));
+        m.set_tile(1, 1, MapTile::new(BattleMapTileType::Outpost));
+        m.set_tile(2, 0, MapTile::new(BattleMapTileType::Road));
+        m.set_tile(2, 1, MapTile::new(BattleMapTileType::Swamp));
+        m.set_tile(2, 2, MapTile::new(BattleMapTileType::Town));
+        m.set_tile(1, 2, MapTile::new(BattleMapTileType::Default));
+        
+        // Act
+        let neighbors_0_0 = m.get_neighbors(0, 0);
+        let neighbors_1_1 = m.get_neighbors(1, 1);
+        let neighbors_2_1 = m.get_neighbors(2, 1);
+
+        // Assert
+        assert_eq!(neighbors_0_0.get_tile_location(), (0,0));
+        assert_eq!(neighbors_0_0.tile_type().get_type(), &BattleMapTileType::Plains);
+        assert_eq!(neighbors_0_0.get_left(), None);
+        assert_eq!(neighbors_0_0.get_upper_left(), None);
+        assert_eq!(neighbors_0_0.get_upper_right(), None);
+        assert_eq!(neighbors_0_0.get_right().unwrap().get_type(), &BattleMapTileType::Forest);
+        assert_eq!(neighbors_0_0.get_lower_right().unwrap().get_type(), &BattleMapTileType::Mountain);
+        assert_eq!(neighbors_0_0.get_lower_left(), None);
+        
+        assert_eq!(neighbors_1_1.get_tile_location(), (1,1));
+        assert_eq!(neighbors_1_1.tile_type().get_type(), &BattleMapTileType::Outpost);
+        assert_eq!(neighbors_1_1.get_left().unwrap().get_type(), &BattleMapTileType::Mountain);
+        assert_eq!(neighbors_1_1.get_upper_left().unwrap().get_type(), &BattleMapTileType::Forest);
+        assert_eq!(neighbors_1_1.get_upper_right().unwrap().get_type(), &BattleMapTileType::Hill);
+        assert_eq!(neighbors_1_1.get_right().unwrap().get_type(), &BattleMapTileType::Default);
+        assert_eq!(neighbors_1_1.get_lower_right().unwrap().get_type(), &BattleMapTileType::Town);
+        assert_eq!(neighbors_1_1.get_lower_left().unwrap().get_type(), &BattleMapTileType::Swamp);
+        
+        assert_eq!(neighbors_2_1.get_tile_location(), (2,1));
+        assert_eq!(neighbors_2_1.tile_type().get_type(), &BattleMapTileType::Swamp);
+        assert_eq!(neighbors_2_1.get_left().unwrap().get_type(), &BattleMapTileType::Road);
+        assert_eq!(neighbors_2_1.get_upper_left().unwrap().get_type(), &BattleMapTileType::Mountain);
+        assert_eq!(neighbors_2_1.get_upper_right().unwrap().get_type(), &BattleMapTileType::Outpost);
+        assert_eq!(neighbors_2_1.get_right().unwrap().get_type(), &BattleMapTileType::Town);
+        assert_eq!(neighbors_2_1.get_lower_right(), None);
+        assert_eq!(neighbors_2_1.get_lower_left(), None);
+    }
+
+
+    #[test]
+    fn tile_owners(){
+
+        // even width, even height
+        let m = MapGenerator::default().create_map(4, 2);
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(1, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(1, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(1, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 3).unwrap().get_owner());
+
+        // even width, odd height
+        let m = MapGenerator::default().create_map(4, 3);
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(1, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(2, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(2, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(2, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(2, 3).unwrap().get_owner());
+        
+        // odd width, even height
+        let m = MapGenerator::default().create_map(5, 2);
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
         assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
         assert_eq!(&TileOwner::Attacker, m.get_tile(0, 3).unwrap().get_owner());
         assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 4).unwrap().get_owner());
@@ -715,22 +4810,1260 @@ mod tests{
         assert_eq!(&TileOwner::Defender, m.get_tile(1, 3).unwrap().get_owner());
         assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 4).unwrap().get_owner());
 
-        // odd width, odd height
-        let mut m = MapGenerator::default().create_map(5, 3);
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 3).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 4).unwrap().get_owner());
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(1, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 3).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 4).unwrap().get_owner());
-        assert_eq!(&TileOwner::LeftFlank, m.get_tile(2, 0).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(2, 1).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(2, 2).unwrap().get_owner());
-        assert_eq!(&TileOwner::Defender, m.get_tile(2, 3).unwrap().get_owner());
-        assert_eq!(&TileOwner::RightFlank, m.get_tile(2, 4).unwrap().get_owner());
+        // odd width, odd height
+        let m = MapGenerator::default().create_map(5, 3);
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(0, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 4).unwrap().get_owner());
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(1, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::SplitAttDef, m.get_tile(1, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(1, 4).unwrap().get_owner());
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(2, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(2, 1).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(2, 2).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(2, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(2, 4).unwrap().get_owner());
+
+        // attacked from the right, the flanks are the top and bottom rows
+        let mut tiles = CampaignGenerationTiles::default();
+        tiles.set_attack_direction(HexDirection::Right);
+        let g = MapGenerator::new(tiles);
+        let m = g.create_map(8, 3);
+        for c in 0..8{
+            assert_eq!(&TileOwner::LeftFlank, m.get_tile(0, c).unwrap().get_owner());
+            assert_eq!(&TileOwner::RightFlank, m.get_tile(2, c).unwrap().get_owner());
+        }
+        assert_eq!(&TileOwner::Defender, m.get_tile(1, 0).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(1, 3).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(1, 4).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(1, 7).unwrap().get_owner());
+        assert_eq!(g.try_create_map(4, 2).unwrap_err(), MapGenError::BoardTooSmall { width: 4, height: 2 });
+
+        // and from the left, mirrored
+        let mut tiles = CampaignGenerationTiles::default();
+        tiles.set_attack_direction(HexDirection::Left);
+        let m = MapGenerator::new(tiles).create_map(20, 3);
+        assert_eq!(&TileOwner::RightFlank, m.get_tile(0, 10).unwrap().get_owner());
+        assert_eq!(&TileOwner::Attacker, m.get_tile(1, 9).unwrap().get_owner());
+        assert_eq!(&TileOwner::Defender, m.get_tile(1, 10).unwrap().get_owner());
+        assert_eq!(&TileOwner::LeftFlank, m.get_tile(2, 19).unwrap().get_owner());
+    }
+
+    #[test]
+    fn campaign_tile_parse(){
+        let t: CampaignMapTile = "forest+river_bridge".parse().unwrap();
+        assert_eq!(t.get_type(), &CampaignMapTileType::Forest);
+        assert_eq!(t.get_add_on(), Some(&CampaignMapTileAddOn::RiverBridge));
+        assert_eq!(t.to_string(), "Forest+RiverBridge");
+        assert_eq!(t.to_string().parse::<CampaignMapTile>().unwrap(), t);
+
+        let t: CampaignMapTile = "Town".parse().unwrap();
+        assert_eq!(t, CampaignMapTile::new(CampaignMapTileType::Town, None));
+        assert_eq!(t.get_add_on(), None);
+
+        assert!("Volcano".parse::<CampaignMapTileType>().is_err());
+        assert!("Plains+Tunnel".parse::<CampaignMapTile>().is_err());
+    }
+
+    fn sector_generator(similarity: f64) -> MapGenerator{
+        let mut g = MapGenerator::new(CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::Hill, None),
+            CampaignMapTile::new(CampaignMapTileType::Forest, None),
+            CampaignMapTile::new(CampaignMapTileType::Swamp, None),
+            CampaignMapTile::new(CampaignMapTileType::Mountain, None),
+            0, 0, 0, 0, 1));
+        g.set_campaign_similarity(similarity);
+        g
+    }
+
+    #[test]
+    fn sector_terrain(){
+        let m = sector_generator(1.0).create_map(8, 5);
+        for r in 0..5{
+            for c in 0..8{
+                let t = m.get_tile(r, c).unwrap();
+                let expected: &[BattleMapTileType] = match t.get_owner(){
+                    TileOwner::Attacker => &[BattleMapTileType::Hill],
+                    TileOwner::Defender => &[BattleMapTileType::Forest],
+                    TileOwner::LeftFlank => &[BattleMapTileType::Swamp],
+                    TileOwner::RightFlank => &[BattleMapTileType::Mountain],
+                    TileOwner::SplitAttDef => &[BattleMapTileType::Hill, BattleMapTileType::Forest],
+                };
+                assert!(expected.contains(t.get_type()), "({},{}) was {:?}", r, c, t.get_type());
+            }
+        }
+
+        // no similarity leaves everything as plains
+        let m = sector_generator(0.0).create_map(8, 5);
+        for r in 0..5{
+            for c in 0..8{
+                assert_eq!(m.get_tile(r, c).unwrap().get_type(), &BattleMapTileType::Plains);
+            }
+        }
+    }
+
+    /// Groups of neighboring town hexes
+    fn settlements(m: &Map) -> Vec<Vec<(usize, usize)>>{
+        let mut towns = m.locations_of(&BattleMapTileType::Town);
+        let mut groups = vec![];
+        while let Some(first) = towns.pop(){
+            let mut group = vec![first];
+            let mut i = 0;
+            while i < group.len(){
+                for (_, n) in m.neighbor_locations(group[i].0, group[i].1){
+                    if let Some(p) = towns.iter().position(|t| *t == n){
+                        group.push(towns.remove(p));
+                    }
+                }
+                i += 1;
+            }
+            groups.push(group);
+        }
+        groups
+    }
+
+    /// Connections are mirrored by the neighbor and the whole network can be walked from any one hex
+    fn assert_connected(m: &Map, network: &HexNetwork){
+        let locations = network.locations();
+        for l in locations.iter(){
+            for d in network.get_connections(*l){
+                if let Some(n) = m.neighbor_location(l.0, l.1, *d){
+                    assert!(network.get_connections(n).contains(&d.opposite()), "{:?} -> {:?} not mirrored", l, n);
+                }
+            }
+        }
+        let mut seen = vec![locations[0]];
+        let mut i = 0;
+        while i < seen.len(){
+            let l = seen[i];
+            for d in network.get_connections(l){
+                if let Some(n) = m.neighbor_location(l.0, l.1, *d){
+                    if !seen.contains(&n){
+                        seen.push(n);
+                    }
+                }
+            }
+            i += 1;
+        }
+        assert_eq!(seen.len(), locations.len());
+    }
+
+    #[test]
+    fn river_courses(){
+        let river = CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::River));
+        let tiles = CampaignGenerationTiles::new(
+            river.clone(), CampaignMapTile::new(CampaignMapTileType::River, None),
+            river, CampaignMapTile::default(), 0, 0, 0, 0, 1);
+        assert_eq!(rivers::river_edges(&tiles), vec![BoardEdge::Top, BoardEdge::Bottom, BoardEdge::Left]);
+
+        for _ in 0..20{
+            let m = MapGenerator::new(tiles.clone()).create_map(9, 7);
+            let rivers = m.get_rivers();
+            assert_connected(&m, rivers);
+            for l in rivers.locations(){
+                assert_eq!(m.get_tile(l.0, l.1).unwrap().get_type(), &BattleMapTileType::River);
+            }
+            for edge in [BoardEdge::Top, BoardEdge::Bottom, BoardEdge::Left]{
+                assert!(m.edge_locations(edge).iter().any(|l| rivers.contains(*l) && rivers.is_exit(&m, *l)), "no exit on {:?}", edge);
+            }
+            assert!(rivers.locations().iter().any(|l| rivers::river_tile_type(&m, *l) == Some(RiverTileType::Branch120)
+                || rivers::river_tile_type(&m, *l) == Some(RiverTileType::BranchY)));
+        }
+
+        // no river tiles means no rivers
+        let m = MapGenerator::default().create_map(9, 7);
+        assert!(m.get_rivers().is_empty());
+    }
+
+    #[test]
+    fn river_tile_types(){
+        use HexDirection::*;
+        assert_eq!(RiverTileType::from_connections(&[UpperLeft]), RiverTileType::Lake);
+        assert_eq!(RiverTileType::from_connections(&[Right, Left]), RiverTileType::Straight);
+        assert_eq!(RiverTileType::from_connections(&[LowerRight, UpperRight]), RiverTileType::Turn120);
+        assert_eq!(RiverTileType::from_connections(&[Right, LowerLeft, UpperLeft]), RiverTileType::BranchY);
+        assert_eq!(RiverTileType::from_connections(&[Right, LowerRight, Left]), RiverTileType::Branch120);
+    }
+
+    #[test]
+    fn board_edges(){
+        let m = MapGenerator::default().create_map(4, 3);
+        // the right neighbor of the last column is off the board rather than the next row
+        assert_eq!(m.get_tile(0, 4), None);
+        assert_eq!(m.neighbor_location(0, 3, HexDirection::Right), None);
+        assert_eq!(m.neighbor_location(1, 3, HexDirection::LowerRight), None);
+        assert_eq!(m.neighbor_location(1, 3, HexDirection::LowerLeft), Some((2, 3)));
+        assert_eq!(m.neighbor_location(2, 0, HexDirection::UpperLeft), None);
+        assert_eq!(m.direction_to((1, 1), (0, 2)), Some(HexDirection::UpperRight));
+        assert_eq!(m.neighbor_locations(1, 1).len(), 6);
+    }
+
+    #[test]
+    fn road_network(){
+        let road = CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::Road));
+        let tiles = CampaignGenerationTiles::new(
+            road.clone(), CampaignMapTile::default(),
+            CampaignMapTile::default(), CampaignMapTile::new(CampaignMapTileType::Road, None), 2, 0, 1, 1, 1);
+        assert_eq!(roads::road_edges(&tiles), vec![BoardEdge::Top, BoardEdge::Right]);
+
+        for _ in 0..20{
+            let m = MapGenerator::new(tiles.clone()).create_map(12, 9);
+            let network = m.get_roads();
+            assert_connected(&m, network);
+            for edge in [BoardEdge::Top, BoardEdge::Right]{
+                assert!(m.edge_locations(edge).iter().any(|l| network.is_exit(&m, *l)), "no exit on {:?}", edge);
+            }
+            let towns = settlements(&m);
+            assert!(!towns.is_empty());
+            for t in towns{
+                assert!(t.iter().any(|h| network.contains(*h)), "town {:?} not on the road", t);
+            }
+            for l in m.locations_of(&BattleMapTileType::Road){
+                assert!(network.contains(l));
+            }
+        }
+
+        // open ground is cheapest, swamps and mountains are avoided
+        assert!(roads::road_cost(&BattleMapTileType::Plains) < roads::road_cost(&BattleMapTileType::Swamp));
+        assert!(roads::road_cost(&BattleMapTileType::Swamp) < roads::road_cost(&BattleMapTileType::Mountain));
+
+        // no roads or towns means no road network
+        let m = MapGenerator::default().create_map(9, 7);
+        assert!(m.get_roads().is_empty());
+    }
+
+    #[test]
+    fn road_tile_types(){
+        use HexDirection::*;
+        assert_eq!(RoadTileType::from_connections(&[UpperLeft]), None);
+        assert_eq!(RoadTileType::from_connections(&[LowerRight, UpperLeft]), Some(RoadTileType::Straight));
+        assert_eq!(RoadTileType::from_connections(&[Right, LowerLeft]), Some(RoadTileType::Turn120));
+        assert_eq!(RoadTileType::from_connections(&[LowerRight, Left, UpperRight]), Some(RoadTileType::BranchY));
+        assert_eq!(RoadTileType::from_connections(&[Right, LowerLeft, Left]), Some(RoadTileType::Branch120));
+    }
+
+    #[test]
+    fn river_crossings(){
+        // a road running left to right always crosses a river running top to bottom
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::River, None),
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::new(CampaignMapTileType::Road, None),
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::Road)), 0, 0, 0, 0, 1);
+        for _ in 0..20{
+            let m = MapGenerator::new(tiles.clone()).create_map(10, 8);
+            let bridges: Vec<(usize, usize)> = m.get_roads().locations().into_iter()
+                .filter(|l| m.get_rivers().contains(*l))
+                .collect();
+            assert!(!bridges.is_empty());
+            for l in bridges{
+                assert_eq!(m.get_tile(l.0, l.1).unwrap().get_crossing(), Some(&RiverCrossing::BridgeWood));
+            }
+            for r in 0..8{
+                for c in 0..10{
+                    if m.get_tile(r, c).unwrap().get_crossing().is_some(){
+                        assert!(m.get_rivers().contains((r, c)));
+                    }
+                }
+            }
+        }
+
+        // campaign bridges and fords are always present even without a road
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::RiverBridge)),
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::RiverFord)),
+            CampaignMapTile::default(), CampaignMapTile::default(), 0, 0, 0, 0, 1);
+        for _ in 0..20{
+            let m = MapGenerator::new(tiles.clone()).create_map(10, 8);
+            let crossings: Vec<RiverCrossing> = m.get_rivers().locations().iter()
+                .filter_map(|l| m.get_tile(l.0, l.1).unwrap().get_crossing().copied())
+                .collect();
+            assert!(crossings.contains(&RiverCrossing::BridgeWood));
+            assert!(crossings.contains(&RiverCrossing::Ford));
+        }
+
+        // a campaign ford is kept on rivers with no shallows
+        let mut g = MapGenerator::new(CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::RiverFord)),
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::default(), CampaignMapTile::default(), 0, 0, 0, 0, 1));
+        for seed in 0..100{
+            g.set_seed(seed);
+            let m = g.create_map(15, 11);
+            assert!(m.get_rivers().locations().iter().any(|l| m.get_tile(l.0, l.1).unwrap().get_crossing() == Some(&RiverCrossing::Ford)), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn wfc_terrain(){
+        let m = MapGenerator::default().create_map(12, 9);
+        let rules = wfc::battle_terrain_rules();
+        let mut solver = WfcSolver::new(rules.clone());
+        solver.restrict(&m, (4, 5), &[BattleMapTileType::Mountain]).unwrap();
+        let tiles = solver.solve(&m, 7).unwrap();
+
+        assert_eq!(tiles[4 * 12 + 5], BattleMapTileType::Mountain);
+        for r in 0..9{
+            for c in 0..12{
+                for (d, n) in m.neighbor_locations(r, c){
+                    assert!(rules.is_allowed(&tiles[r * 12 + c], d, &tiles[n.0 * 12 + n.1]));
+                }
+            }
+        }
+
+        // same seed, same map
+        assert_eq!(solver.solve(&m, 7), Some(tiles));
+
+        // restrictions must be on the board and use tiles from the rules
+        assert!(matches!(solver.restrict(&m, (0, 12), &[BattleMapTileType::Hill]), Err(MapGenError::OutOfBounds { .. })));
+        assert_eq!(solver.restrict(&m, (0, 0), &[BattleMapTileType::Default]), Err(MapGenError::UnknownTile));
+        assert!(!rules.is_allowed(&BattleMapTileType::Default, HexDirection::Left, &BattleMapTileType::Plains));
+        let mut small = WfcSolver::new(rules.clone());
+        small.restrict(&m, (8, 11), &[BattleMapTileType::Plains]).unwrap();
+        assert_eq!(small.solve(&MapGenerator::default().create_map(4, 2), 7), None);
+
+        // the generator fills the free ground from the rules around the rivers, roads and towns it placed
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::new(CampaignMapTileType::Town, Some(CampaignMapTileAddOn::Road)),
+            CampaignMapTile::new(CampaignMapTileType::Swamp, None),
+            CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::Road)),
+            1, 1, 1, 1, 1,
+        );
+        let mut g = MapGenerator::new(tiles);
+        g.set_wfc_terrain(true);
+        for seed in 0..5{
+            g.set_seed(seed);
+            let m = g.create_map(20, 14);
+            assert!(!m.get_rivers().is_empty() && !m.get_roads().is_empty());
+            for l in m.get_rivers().locations(){
+                assert_eq!(m.get_tile(l.0, l.1).unwrap().get_type(), &BattleMapTileType::River);
+            }
+            for r in 0..14{
+                for c in 0..20{
+                    let t = m.get_tile(r, c).unwrap().get_type();
+                    assert_ne!(t, &BattleMapTileType::Default);
+                    for (d, n) in m.neighbor_locations(r, c){
+                        assert!(rules.is_allowed(t, d, m.get_tile(n.0, n.1).unwrap().get_type()), "seed {} ({},{})", seed, r, c);
+                    }
+                }
+            }
+            assert!(!m.locations_of(&BattleMapTileType::Forest).is_empty());
+            assert_eq!(g.create_map(20, 14), m);
+        }
+        assert!(g.get_wfc_terrain());
+    }
+
+    #[test]
+    fn wfc_backtracking(){
+        let m = MapGenerator::default().create_map(10, 8);
+
+        // every hex touches a triangle of neighbors, so three colors are needed and two are not enough
+        let mut rules = WfcRules::new();
+        for t in 0..3{
+            rules.add_tile(t, 1.0).unwrap();
+        }
+        for a in 0..3{
+            for b in 0..3{
+                if a != b{
+                    rules.allow_all(&a, &b).unwrap();
+                }
+            }
+        }
+        for seed in 0..10{
+            let tiles = WfcSolver::new(rules.clone()).solve(&m, seed).unwrap();
+            for r in 0..8{
+                for c in 0..10{
+                    for (_, n) in m.neighbor_locations(r, c){
+                        assert_ne!(tiles[r * 10 + c], tiles[n.0 * 10 + n.1]);
+                    }
+                }
+            }
+        }
+
+        let mut rules = WfcRules::new();
+        rules.add_tile(0, 1.0).unwrap();
+        rules.add_tile(1, 1.0).unwrap();
+        rules.allow_all(&0, &1).unwrap();
+        assert_eq!(rules.allow_all(&0, &2), Err(MapGenError::UnknownTile));
+        assert_eq!(WfcSolver::new(rules.clone()).solve(&m, 0), None);
+
+        for t in 2..64{
+            rules.add_tile(t, 1.0).unwrap();
+        }
+        assert_eq!(rules.add_tile(64, 1.0), Err(MapGenError::TooManyTiles { max: 64 }));
+        assert_eq!(rules.add_tile(5, 2.0), Ok(()));
+    }
+
+    #[test]
+    fn seeded_generation(){
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::Road)),
+            CampaignMapTile::new(CampaignMapTileType::Town, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::new(CampaignMapTileType::Hill, Some(CampaignMapTileAddOn::RiverFord)),
+            CampaignMapTile::new(CampaignMapTileType::Swamp, Some(CampaignMapTileAddOn::Road)), 2, 1, 1, 2, 2);
+        let mut g = MapGenerator::new(tiles.clone());
+        g.set_seed(1234);
+        let m = g.create_map(15, 11);
+        assert_eq!(m.get_seed(), 1234);
+        assert_eq!(g.create_map(15, 11), m);
+
+        let mut other = MapGenerator::new(tiles);
+        other.set_seed(1235);
+        assert_ne!(other.create_map(15, 11), m);
+
+        // an unseeded map can be rebuilt from the seed it records
+        let unseeded = MapGenerator::default().create_map(8, 6);
+        let mut g = MapGenerator::default();
+        g.set_seed(unseeded.get_seed());
+        assert_eq!(g.create_map(8, 6), unseeded);
+    }
+
+    #[test]
+    fn png_render(){
+        let mut g = MapGenerator::default();
+        g.set_seed(3);
+        let mut m = g.create_map(6, 5);
+        m.set_tile_type(2, 3, BattleMapTileType::River);
+
+        let mut renderer = PngRenderer::new(20);
+        renderer.set_show_coordinates(false);
+        let canvas = renderer.render(&m);
+        let layout = renderer.layout();
+        for (r, c) in [(0, 0), (1, 5), (2, 3), (4, 2)]{
+            let (x, y) = layout.center(r, c);
+            let expected = render::tile_colour(m.get_tile(r, c).unwrap().get_type());
+            assert_eq!(canvas.get_pixel(x as u32, y as u32), Some(expected), "hex ({},{})", r, c);
+        }
+
+        // owner tinting changes the hex colour
+        renderer.set_show_owners(true);
+        let (x, y) = layout.center(2, 3);
+        assert_ne!(renderer.render(&m).get_pixel(x as u32, y as u32), Some(render::tile_colour(&BattleMapTileType::River)));
+
+        // odd rows sit half a hex right of even rows
+        assert_eq!(layout.center(1, 0).0 - layout.center(0, 0).0, layout.hex_width() / 2.0);
+
+        let mut bytes = vec![];
+        renderer.render(&m).write_png(&mut bytes).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+    }
+
+    #[test]
+    fn svg_export(){
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::River, None),
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::new(CampaignMapTileType::Road, None),
+            CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::Road)), 0, 0, 0, 0, 1);
+        let mut g = MapGenerator::new(tiles);
+        g.set_seed(99);
+        let m = g.create_map(7, 5);
+        let svg = SvgRenderer::default().render(&m);
+
+        assert_eq!(svg.matches("<polygon").count(), 35);
+        assert!(svg.contains(r#"data-seed="99" data-width="7" data-height="5""#));
+        assert!(svg.contains(r#"data-right-flank="Forest+Road""#));
+        assert!(svg.contains(r#"data-attack-direction="UpperRight" data-layout="Classic">"#));
+        assert!(svg.contains(r#"class="hex tile-river owner-attacker"#));
+        assert!(svg.contains("crossing-bridge-wood"));
+        assert!(svg.contains(r#"<polyline class="river""#));
+        assert!(svg.contains(r#"<polyline class="road""#));
+        assert!(svg.contains(".tile-forest { fill:"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+
+        let mut turned = CampaignGenerationTiles::default();
+        turned.set_attack_direction(HexDirection::Left);
+        let mut g = MapGenerator::new(turned);
+        g.set_layout(DeploymentLayout::RiverCrossing);
+        let river_svg = SvgRenderer::default().render(&g.create_map(12, 9));
+        assert!(river_svg.contains(r#"data-attack-direction="Left" data-layout="RiverCrossing">"#));
+
+        let mut unstyled = SvgRenderer::default();
+        unstyled.set_embed_style(false);
+        assert!(!unstyled.render(&m).contains("<style>"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip(){
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::River, None),
+            CampaignMapTile::new(CampaignMapTileType::Town, Some(CampaignMapTileAddOn::RiverBridge)),
+            CampaignMapTile::new(CampaignMapTileType::Road, None),
+            CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::Road)), 2, 1, 1, 1, 2);
+        let mut g = MapGenerator::new(tiles);
+        g.set_seed(5);
+        let m = g.create_map(9, 6);
+
+        let json = m.to_json();
+        assert!(json.contains(r#""version": 1"#));
+        assert!(json.contains(r#""campaign_tiles""#));
+        assert_eq!(Map::from_json(&json), Ok(m.clone()));
+
+        // generator inputs round trip on their own too
+        let g_json = serde_json::to_string(&g).unwrap();
+        let g2: MapGenerator = serde_json::from_str(&g_json).unwrap();
+        assert_eq!(g2.create_map(9, 6), m);
+
+        // a similarity out of range is clamped on the way in
+        let g3: MapGenerator = serde_json::from_str(&g_json.replace(r#""campaign_similarity":0.6"#, r#""campaign_similarity":2.0"#)).unwrap();
+        assert_eq!(g3.get_campaign_similarity(), 1.0);
+        assert!(g3.try_create_map(15, 11).is_ok());
+
+        // dimensions must match the tiles
+        let bad = json.replacen(r#""board_width": 9"#, r#""board_width": 8"#, 1);
+        assert_eq!(Map::from_json(&bad), Err(MapGenError::TileCountMismatch { expected: 48, found: 54 }));
+        let bad = json.replacen(r#""version": 1"#, r#""version": 2"#, 1);
+        assert_eq!(Map::from_json(&bad), Err(MapGenError::UnsupportedVersion { found: 2, expected: 1 }));
+        assert!(matches!(Map::from_json("{}"), Err(MapGenError::InvalidData(_))));
+    }
+
+    #[test]
+    fn fallible_api(){
+        let g = MapGenerator::default();
+        assert_eq!(g.try_create_map(3, 5).unwrap_err(), MapGenError::BoardTooSmall { width: 3, height: 5 });
+        assert_eq!(g.try_create_map(4, 1).unwrap_err(), MapGenError::BoardTooSmall { width: 4, height: 1 });
+
+        // 7 of each feature in a radius of 1 is 3 tiles per tile
+        let dense = MapGenerator::new(CampaignGenerationTiles::new(
+            CampaignMapTile::default(), CampaignMapTile::default(),
+            CampaignMapTile::default(), CampaignMapTile::default(), 7, 0, 7, 7, 1));
+        assert_eq!(dense.try_create_map(4, 2).unwrap_err(), MapGenError::DensityTooHigh { requested: 24, available: 8 });
+
+        // counts far beyond the board are refused rather than overflowing
+        let huge = MapGenerator::new(CampaignGenerationTiles::new(
+            CampaignMapTile::default(), CampaignMapTile::default(),
+            CampaignMapTile::default(), CampaignMapTile::default(), u32::MAX, u32::MAX, u32::MAX, u32::MAX, 0));
+        assert!(matches!(huge.try_create_map(15, 11), Err(MapGenError::DensityTooHigh { .. })));
+        assert_eq!(CampaignGenerationTiles::radius_search_size(100_000), 30_000_300_001);
+        assert_eq!(CampaignGenerationTiles::radius_search_size(u32::MAX), u64::MAX);
+        assert_eq!(g.try_create_map(100_000, 100_000).unwrap_err(), MapGenError::BoardTooLarge { width: 100_000, height: 100_000 });
+        assert_eq!(g.try_create_map(usize::MAX, 2).unwrap_err(), MapGenError::BoardTooLarge { width: usize::MAX, height: 2 });
+
+        let mut m = g.try_create_map(4, 3).unwrap();
+        assert!(m.try_set_tile(2, 3, MapTile::new(BattleMapTileType::Forest)).is_ok());
+        assert_eq!(m.try_set_tile(3, 0, MapTile::new(BattleMapTileType::Forest)),
+            Err(MapGenError::OutOfBounds { row: 3, column: 0, width: 4, height: 3 }));
+        assert!(m.try_set_tile_type(1, 1, BattleMapTileType::Hill).is_ok());
+        assert_eq!(m.get_tile(1, 1).unwrap().get_type(), &BattleMapTileType::Hill);
+        assert!(m.try_set_tile_crossing(0, 4, Some(RiverCrossing::Ford)).is_err());
+        assert!(m.try_set_tile_add_on(3, 3, Some(MapAddOn::Wall)).is_err());
+        assert!(m.try_set_tile_elevation(1, 4, 3).is_err());
+        assert!(m.try_set_tile_type(usize::MAX, 0, BattleMapTileType::Hill).is_err());
+        assert_eq!(m.try_get_neighbors(2, 3).unwrap().get_left().unwrap().get_type(), &BattleMapTileType::Plains);
+        assert!(m.try_get_neighbors(0, 4).is_err());
+        assert!(matches!("Lava".parse::<CampaignMapTileType>(), Err(MapGenError::Parse(_))));
+    }
+
+    #[test]
+    fn hex_coords(){
+        let m = MapGenerator::default().create_map(7, 6);
+        for r in 0..6{
+            for c in 0..7{
+                let coord = HexCoord::from_offset(r, c);
+                assert_eq!(coord.to_offset(), Some((r, c)));
+                let (q, cr, s) = coord.cube();
+                assert_eq!(q + cr + s, 0);
+                assert_eq!(HexCoord::from_cube(q, cr, s), Some(coord));
+                assert_eq!(m.get_tile_at(coord), m.get_tile(r, c));
+
+                // cube neighbors are the same hexes as the offset neighbors
+                let from_coords: Vec<(HexDirection, (usize, usize))> = m.neighbor_coords(coord).iter()
+                    .map(|(d, n)| (*d, n.to_offset().unwrap()))
+                    .collect();
+                assert_eq!(from_coords, m.neighbor_locations(r, c));
+            }
+        }
+        assert_eq!(HexCoord::new(0, -1).to_offset(), None);
+        assert_eq!(HexCoord::new(-1, 1).to_offset(), None);
+        assert_eq!(HexCoord::new(-1, 2).to_offset(), Some((2, 0)));
+        assert_eq!(HexCoord::from_cube(1, 1, 1), None);
+        assert!(!m.contains(HexCoord::new(7, 0)));
+        assert!(m.contains((5, 6)));
+        assert_eq!(m.coords().len(), 42);
+    }
+
+    #[test]
+    fn hex_ranges(){
+        let m = MapGenerator::default().create_map(9, 7);
+        assert_eq!(m.distance((3, 4), (3, 4)), 0);
+        assert_eq!(m.distance((3, 4), (3, 7)), 3);
+        assert_eq!(m.distance((0, 0), (6, 0)), 6);
+        assert_eq!(m.distance((0, 0), (1, 0)), 1);
+        assert_eq!(m.distance((1, 0), (0, 1)), 1);
+        assert_eq!(m.distance(HexCoord::from_offset(2, 2), (4, 3)), 2);
+
+        // unclipped, the counts match the search radius formula
+        for r in 0..5{
+            let spiral = HexCoord::new(4, 4).spiral(r);
+            assert_eq!(spiral.len() as u64, CampaignGenerationTiles::radius_search_size(r));
+            assert_eq!(HexCoord::new(4, 4).ring(r).len() as u32, if r == 0 { 1 } else { 6 * r });
+        }
+
+        // every hex in a ring is at the ring's distance, and the spiral is every hex within range
+        let center = HexCoord::from_offset(3, 4);
+        for r in 0..4{
+            for h in m.ring(center, r){
+                assert_eq!(h.distance(center), r);
+            }
+            let spiral = m.spiral(center, r);
+            let expected: Vec<HexCoord> = m.coords().into_iter().filter(|h| h.distance(center) <= r).collect();
+            assert_eq!(spiral.len(), expected.len());
+            assert_eq!(m.within_range(center, r), expected);
+        }
+        assert_eq!(m.ring(center, 1).len(), m.neighbor_locations(3, 4).len());
+
+        // clipped at the corner of the board
+        assert_eq!(m.within_range((0, 0), 1).len(), 3);
+        assert_eq!(m.ring((0, 0), 1).len(), 2);
+
+        // huge radii stop at the edge of the board instead of building the whole shape
+        assert!(m.ring(center, u32::MAX).is_empty());
+        assert_eq!(m.spiral(center, u32::MAX).len(), m.coords().len());
+        let outside = HexCoord::new(-20, 0);
+        assert_eq!(m.spiral(outside, u32::MAX).len(), m.coords().len());
+        assert!(!m.ring(outside, 20).is_empty());
+    }
+
+    #[test]
+    fn movement_paths(){
+        // a river down column 3 with a single bridge at row 4
+        let mut m = MapGenerator::default().create_map(7, 6);
+        for r in 0..6{
+            for c in 0..7{
+                m.set_tile_type(r, c, BattleMapTileType::Plains);
+                m.set_tile_crossing(r, c, None);
+                m.set_tile_elevation(r, c, 0);
+            }
+        }
+        for r in 0..6{
+            m.set_tile_type(r, 3, BattleMapTileType::River);
+        }
+        m.set_tile_crossing(4, 3, Some(RiverCrossing::BridgeWood));
+        m.set_tile_type(1, 1, BattleMapTileType::Mountain);
+        let costs = MovementCosts::default();
+
+        let path = pathfinding::find_path(&m, (0, 0), (0, 6), &costs).unwrap();
+        assert!(path.get_hexes().contains(&(4, 3)));
+        assert!(!path.get_hexes().contains(&(1, 1)));
+        assert_eq!(path.get_hexes().first(), Some(&(0, 0)));
+        assert_eq!(path.get_hexes().last(), Some(&(0, 6)));
+        for w in path.get_hexes().windows(2){
+            assert_eq!(m.distance(w[0], w[1]), 1);
+        }
+
+        // A* agrees with the full cost field
+        let field = pathfinding::cost_field(&m, (0, 0), &costs);
+        assert_eq!(field.get_cost(0, 6), Some(path.get_cost()));
+        assert_eq!(field.path_to(0, 6).unwrap().get_cost(), path.get_cost());
+        assert_eq!(field.get_cost(0, 0), Some(0));
+        assert_eq!(field.get_cost(2, 3), None);
+        assert_eq!(path.turns(4), path.get_cost().div_ceil(4));
+
+        // without the bridge the far bank is out of reach
+        m.set_tile_crossing(4, 3, None);
+        assert_eq!(pathfinding::find_path(&m, (0, 0), (0, 6), &costs), None);
+
+        // unless the costs let units swim
+        let mut swimming = MovementCosts::default();
+        swimming.set_cost(BattleMapTileType::River, Some(10));
+        assert_eq!(pathfinding::find_path(&m, (0, 0), (0, 6), &swimming).unwrap().get_cost(), 2 * 2 + 10 + 3 * 2);
+
+        // costs too large to add up leave the far hexes unreachable instead of overflowing
+        let mut huge = MovementCosts::default();
+        huge.set_cost(BattleMapTileType::Plains, Some(u32::MAX / 2));
+        assert_eq!(pathfinding::find_path(&m, (0, 0), (0, 2), &huge).unwrap().get_cost(), u32::MAX - 1);
+        assert_eq!(pathfinding::find_path(&m, (0, 0), (0, 6), &huge), None);
+        let field = pathfinding::cost_field(&m, (0, 0), &huge);
+        assert_eq!(field.get_cost(0, 1), Some(u32::MAX / 2));
+        assert_eq!(field.get_cost(5, 0), None);
+    }
+
+    #[test]
+    fn line_of_sight(){
+        let a = HexCoord::from_offset(0, 0);
+        let b = HexCoord::from_offset(5, 4);
+        let line = a.line_to(b);
+        assert_eq!(line.len() as u32, a.distance(b) + 1);
+        assert_eq!((line[0], line[line.len() - 1]), (a, b));
+        for w in line.windows(2){
+            assert_eq!(w[0].distance(w[1]), 1);
+        }
+        let mut reversed = b.line_to(a);
+        reversed.reverse();
+        assert_eq!(line, reversed);
+
+        let mut m = MapGenerator::default().create_map(7, 5);
+        for r in 0..5{
+            for c in 0..7{
+                m.set_tile_type(r, c, BattleMapTileType::Plains);
+                m.set_tile_elevation(r, c, 0);
+            }
+        }
+        let rules = SightRules::default();
+        assert!(m.line_of_sight((2, 0), (2, 6), &rules));
+
+        // a forest in the way blocks sight along the row, both ways
+        m.set_tile_type(2, 3, BattleMapTileType::Forest);
+        assert!(!m.line_of_sight((2, 0), (2, 6), &rules));
+        assert!(!m.line_of_sight((2, 6), (2, 0), &rules));
+        // but not into the forest itself
+        assert!(m.line_of_sight((2, 0), (2, 3), &rules));
+
+        // a hill sees over it, a mountain does not
+        m.set_tile_type(2, 0, BattleMapTileType::Hill);
+        assert!(m.line_of_sight((2, 0), (2, 6), &rules));
+        m.set_tile_type(2, 3, BattleMapTileType::Mountain);
+        assert!(!m.line_of_sight((2, 0), (2, 6), &rules));
+
+        // rules are configurable per type
+        let mut open = SightRules::default();
+        open.set_rule(BattleMapTileType::Mountain, 0, 0);
+        assert!(m.line_of_sight((2, 0), (2, 6), &open));
+
+        let seen = m.visibility_map((2, 0), &rules);
+        assert_eq!(seen.len(), 35);
+        assert!(seen[2 * 7]);
+        assert!(!seen[2 * 7 + 6]);
+        assert!(seen[2 * 7 + 3]);
+        assert!(!m.line_of_sight((2, 0), (9, 9), &rules));
+    }
+
+    #[test]
+    fn elevation_terrain(){
+        // flat campaign tiles so every hill and mountain comes from the heightmap
+        let plains = CampaignMapTile::new(CampaignMapTileType::Plains, None);
+        let tiles = CampaignGenerationTiles::new(plains.clone(), plains.clone(), plains.clone(), plains, 0, 0, 2, 2, 1);
+        let mut g = MapGenerator::new(tiles.clone());
+        g.set_seed(11);
+        let m = g.create_map(20, 12);
+
+        // mountains are the highest ground, hills the slopes below them
+        let heights = |t: BattleMapTileType| -> Vec<u32> {
+            m.locations_of(&t).iter().map(|l| m.get_tile(l.0, l.1).unwrap().get_elevation()).collect()
+        };
+        let (mountains, hills, plains) = (heights(BattleMapTileType::Mountain), heights(BattleMapTileType::Hill), heights(BattleMapTileType::Plains));
+        assert!(!mountains.is_empty() && !hills.is_empty());
+        assert!(mountains.iter().min() > hills.iter().max());
+        assert!(hills.iter().min() > plains.iter().max());
+
+        // and form ranges rather than lone peaks
+        let ranged = m.locations_of(&BattleMapTileType::Mountain).iter()
+            .filter(|l| m.neighbor_locations(l.0, l.1).iter().any(|(_, n)| m.get_tile(n.0, n.1).unwrap().get_type() == &BattleMapTileType::Mountain))
+            .count();
+        assert!(ranged * 10 >= mountains.len() * 9);
+
+        // rougher campaigns reach higher
+        let rough = elevation::roughness(&tiles);
+        assert!(rough > 0.0);
+        assert!(elevation::max_elevation(rough) > elevation::max_elevation(0.0));
+        let flat = elevation::generate_heightmap(20, 12, 0.0, &mut ChaCha8Rng::seed_from_u64(3));
+        assert_eq!(flat.len(), 240);
+        assert_eq!(flat.iter().max(), Some(&elevation::max_elevation(0.0)));
+        assert_eq!(elevation::threshold_for_count(&[0, 1, 1, 2, 3], 4), Some(1));
+        assert_eq!(elevation::threshold_for_count(&[0, 1, 1, 2, 3], 3), Some(2));
+        assert_eq!(elevation::threshold_for_count(&[0, 1, 1, 2, 3], 0), None);
+
+        // climbing costs movement and high ground sees over a ridge that blocks the valley
+        let mut v = MapGenerator::default().create_map(7, 2);
+        for c in 0..7{
+            v.set_tile_type(0, c, BattleMapTileType::Plains);
+            v.set_tile_crossing(0, c, None);
+            v.set_tile_elevation(0, c, 0);
+        }
+        v.set_tile_elevation(0, 3, 2);
+        let costs = MovementCosts::default();
+        let up = costs.step_cost(v.get_tile(0, 2).unwrap(), v.get_tile(0, 3).unwrap()).unwrap();
+        let down = costs.step_cost(v.get_tile(0, 3).unwrap(), v.get_tile(0, 4).unwrap()).unwrap();
+        assert_eq!(up, down + 2 * costs.get_climb_cost());
+        let rules = SightRules::default();
+        assert!(!v.line_of_sight((0, 0), (0, 6), &rules));
+        v.set_tile_elevation(0, 0, 2);
+        assert!(v.line_of_sight((0, 0), (0, 6), &rules));
+    }
+
+    #[test]
+    fn forest_swamp_clusters(){
+        let tile = |t: CampaignMapTileType, a: Option<CampaignMapTileAddOn>| CampaignMapTile::new(t, a);
+        let generate = |attacker: CampaignMapTileType, right: CampaignMapTileType| {
+            let mut g = MapGenerator::new(CampaignGenerationTiles::new(
+                tile(attacker, None),
+                tile(CampaignMapTileType::Plains, None),
+                tile(CampaignMapTileType::Swamp, Some(CampaignMapTileAddOn::River)),
+                tile(right, None),
+                0, 0, 0, 0, 1));
+            g.set_campaign_similarity(0.5);
+            g.set_seed(5);
+            g.create_map(20, 12)
+        };
+        let m = generate(CampaignMapTileType::Forest, CampaignMapTileType::Plains);
+        let of_type = |m: &Map, t: BattleMapTileType| m.locations_of(&t);
+        let clustered = |m: &Map, locations: &[(usize, usize)], t: BattleMapTileType| locations.iter()
+            .filter(|l| m.neighbor_locations(l.0, l.1).iter().any(|(_, n)| m.get_tile(n.0, n.1).unwrap().get_type() == &t))
+            .count();
+        let riverside = |m: &Map, l: &(usize, usize)| m.neighbor_locations(l.0, l.1).iter().any(|(_, n)| m.get_rivers().contains(*n));
+
+        // forests grow in clumps in the attacker's sector
+        let forests = of_type(&m, BattleMapTileType::Forest);
+        assert!(!forests.is_empty());
+        assert!(clustered(&m, &forests, BattleMapTileType::Forest) * 10 >= forests.len() * 8);
+        for l in forests.iter(){
+            assert!(matches!(m.get_tile(l.0, l.1).unwrap().get_owner(), TileOwner::Attacker | TileOwner::SplitAttDef));
+        }
+
+        // swamps stay on the left flank and favour the river's banks
+        let swamps = of_type(&m, BattleMapTileType::Swamp);
+        assert!(!swamps.is_empty());
+        assert!(clustered(&m, &swamps, BattleMapTileType::Swamp) * 10 >= swamps.len() * 8);
+        let flank: Vec<(usize, usize)> = (0..12).flat_map(|r| (0..20).map(move |c| (r, c)))
+            .filter(|l| m.get_tile(l.0, l.1).unwrap().get_owner() == &TileOwner::LeftFlank && !m.get_rivers().contains(*l))
+            .collect();
+        assert!(swamps.iter().all(|l| flank.contains(l)));
+        let swamp_banks = swamps.iter().filter(|l| riverside(&m, l)).count() as f64 / swamps.len() as f64;
+        let flank_banks = flank.iter().filter(|l| riverside(&m, l)).count() as f64 / flank.len() as f64;
+        assert!(swamp_banks > flank_banks);
+
+        // more forested campaign tiles give more forest
+        let wooded = generate(CampaignMapTileType::Forest, CampaignMapTileType::Forest);
+        assert!(of_type(&wooded, BattleMapTileType::Forest).len() > forests.len());
+        assert_eq!(of_type(&generate(CampaignMapTileType::Plains, CampaignMapTileType::Plains), BattleMapTileType::Forest).len(), 0);
+
+        // clusters take distinct candidates only, the heaviest almost surely first
+        let candidates: Vec<(usize, usize)> = (0..12).flat_map(|r| (0..10).map(move |c| (r, c))).collect();
+        let mut gen = ChaCha8Rng::seed_from_u64(3);
+        let chosen = clusters::grow_clusters(&m, &candidates, 60, |l| match l == (7, 4){
+            true => 1_000_000,
+            false => 1,
+        }, &mut gen);
+        assert_eq!(chosen.first(), Some(&(7, 4)));
+        assert_eq!(chosen.len(), 60);
+        assert_eq!(chosen.iter().collect::<std::collections::BTreeSet<_>>().len(), 60);
+        assert!(chosen.iter().all(|l| l.1 < 10));
+        assert_eq!(clusters::grow_clusters(&m, &candidates, 500, |_| 1, &mut gen).len(), candidates.len());
+    }
+
+    #[test]
+    fn town_placement(){
+        // a town defender with a road and river running through the battle
+        let tiles = CampaignGenerationTiles::new(
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::Road)),
+            CampaignMapTile::new(CampaignMapTileType::Town, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::River)),
+            CampaignMapTile::default(), 2, 1, 0, 1, 1);
+        assert_eq!(towns::town_owners(&tiles), vec![TileOwner::Defender]);
+        assert!(towns::town_size(tiles.city_density()) > 1);
+        assert_eq!(towns::town_size(0.0), 1);
+        assert_eq!(towns::town_size(10.0), towns::MAX_TOWN_HEXES);
+
+        for seed in 0..10{
+            let mut g = MapGenerator::new(tiles.clone());
+            g.set_seed(seed);
+            let m = g.create_map(16, 12);
+            let groups = settlements(&m);
+            assert!(!groups.is_empty());
+            assert!(groups.iter().any(|t| t.len() > 1));
+            for t in groups.iter(){
+                assert!(t.len() <= towns::MAX_TOWN_HEXES);
+                assert!(t.iter().any(|h| m.get_roads().contains(*h)), "town {:?} not on the road", t);
+                for h in t.iter(){
+                    assert!(matches!(m.get_tile(h.0, h.1).unwrap().get_owner(), TileOwner::Defender | TileOwner::SplitAttDef));
+                }
+            }
+        }
+
+        // with no town campaign tiles settlements may go anywhere
+        let mut g = MapGenerator::new(CampaignGenerationTiles::new(
+            CampaignMapTile::default(), CampaignMapTile::default(),
+            CampaignMapTile::default(), CampaignMapTile::default(), 3, 0, 0, 0, 1));
+        g.set_seed(2);
+        let m = g.create_map(16, 12);
+        assert_eq!(m.locations_of(&BattleMapTileType::Town).len(), 3 * 192 / 7);
+    }
+
+    #[test]
+    fn fortifications(){
+        let generate = |defender: CampaignMapTileType| {
+            let mut g = MapGenerator::new(CampaignGenerationTiles::new(
+                CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::Road)),
+                CampaignMapTile::new(defender, Some(CampaignMapTileAddOn::Road)),
+                CampaignMapTile::default(), CampaignMapTile::default(), 1, 0, 0, 1, 1));
+            g.set_seed(8);
+            g.create_map(16, 12)
+        };
+        let add_ons = |m: &Map| -> Vec<((usize, usize), MapAddOn)> {
+            (0..12).flat_map(|r| (0..16).map(move |c| (r, c)))
+                .filter_map(|l| m.get_tile(l.0, l.1).unwrap().get_add_on().map(|a| (l, *a)))
+                .collect()
+        };
+
+        let town = generate(CampaignMapTileType::Town);
+        let fortified = add_ons(&town);
+        assert!(!fortified.is_empty());
+        for (l, a) in fortified.iter(){
+            let tile = town.get_tile(l.0, l.1).unwrap();
+            assert_eq!(tile.get_owner(), &TileOwner::Defender);
+            assert_ne!(tile.get_type(), &BattleMapTileType::Mountain);
+            assert_eq!(*a, fortifications::add_on_for(tile, &CampaignMapTileType::Town));
+            match tile.get_type(){
+                BattleMapTileType::Road => assert_eq!(*a, MapAddOn::Barricade),
+                BattleMapTileType::Town => assert_eq!(*a, MapAddOn::Wall),
+                _ => {},
+            }
+        }
+
+        // roads are fortified far more often than open ground
+        let defended: Vec<(usize, usize)> = (0..12).flat_map(|r| (0..16).map(move |c| (r, c)))
+            .filter(|l| town.get_tile(l.0, l.1).unwrap().get_owner() == &TileOwner::Defender)
+            .collect();
+        let share = |on_road: bool| {
+            let hexes: Vec<&(usize, usize)> = defended.iter().filter(|l| town.get_roads().contains(**l) == on_road).collect();
+            hexes.iter().filter(|l| fortified.iter().any(|(f, _)| f == **l)).count() as f64 / hexes.len() as f64
+        };
+        assert!(share(true) > share(false));
+
+        // an outpost is fortified more heavily than a town, and open country barely at all
+        let outpost = add_ons(&generate(CampaignMapTileType::Outpost));
+        let open = add_ons(&generate(CampaignMapTileType::Plains));
+        assert!(outpost.len() > fortified.len());
+        assert!(fortified.len() > open.len());
+        assert!(outpost.iter().any(|(_, a)| *a == MapAddOn::Palisade));
+        assert!(open.iter().all(|(_, a)| *a != MapAddOn::Palisade));
+
+        assert!(SvgRenderer::default().render(&town).contains("add-on-barricade"));
+
+        // a barricaded bridge still prints as a bridge
+        let mut bridge = MapTile::new(BattleMapTileType::River);
+        bridge.set_crossing(Some(RiverCrossing::BridgeStone));
+        assert_eq!(bridge.tile_type_string().bgcolor(), Some(colored::Color::Cyan));
+        bridge.set_add_on(Some(fortifications::add_on_for(&bridge, &CampaignMapTileType::Town)));
+        assert_eq!(&*bridge.tile_type_string(), "B");
+        assert_eq!(bridge.tile_type_string().bgcolor(), Some(colored::Color::Red));
+    }
+
+    #[test]
+    fn outpost_generation(){
+        for edge in BoardEdge::ALL{
+            let owner = edge.owner(HexDirection::UpperRight);
+            let mut tiles = [CampaignMapTile::default(), CampaignMapTile::default(), CampaignMapTile::default(), CampaignMapTile::default()];
+            let i = BoardEdge::ALL.iter().position(|e| *e == edge).unwrap();
+            tiles[i] = CampaignMapTile::new(CampaignMapTileType::Outpost, None);
+            let [top, bottom, left, right] = tiles;
+            let tiles = CampaignGenerationTiles::new(top, bottom, left, right, 0, 0, 1, 1, 1);
+            assert_eq!(outposts::outpost_owners(&tiles), vec![owner.clone()]);
+            assert_eq!(BoardEdge::of_owner(&owner, tiles.get_attack_direction()), Some(edge));
+
+            for seed in 0..5{
+                let mut g = MapGenerator::new(tiles.clone());
+                g.set_seed(seed);
+                let m = g.create_map(16, 12);
+                let found = m.locations_of(&BattleMapTileType::Outpost);
+                assert_eq!(found.len(), 1, "{:?} seed {}", edge, seed);
+                let outpost = found[0];
+                let tile = m.get_tile(outpost.0, outpost.1).unwrap();
+                assert_eq!(tile.get_owner(), &owner);
+
+                // on the highest open ground of its region
+                for (r, c) in (0..12).flat_map(|r| (0..16).map(move |c| (r, c))){
+                    let other = m.get_tile(r, c).unwrap();
+                    if other.get_owner() == &owner && matches!(other.get_type(), BattleMapTileType::Hill | BattleMapTileType::Plains | BattleMapTileType::Forest | BattleMapTileType::Swamp){
+                        assert!(tile.get_elevation() >= other.get_elevation());
+                    }
+                }
+
+                // a road runs from it off the map
+                let roads = m.get_roads();
+                assert!(roads.contains(outpost));
+                assert_connected(&m, roads);
+                assert!(roads.locations().iter().any(|l| roads.is_exit(&m, *l)));
+
+                // behind a palisade and a ring of barricades
+                assert_eq!(tile.get_add_on(), Some(&MapAddOn::Palisade));
+                for (_, n) in m.neighbor_locations(outpost.0, outpost.1){
+                    let t = m.get_tile(n.0, n.1).unwrap();
+                    if fortifications::fortifiable(t){
+                        assert_eq!(t.get_add_on(), Some(&MapAddOn::Barricade));
+                    }
+                }
+            }
+        }
+
+        // no outpost campaign tiles, no outposts
+        assert!(MapGenerator::default().create_map(10, 8).locations_of(&BattleMapTileType::Outpost).is_empty());
+    }
+
+    #[test]
+    fn map_formats(){
+        let mut g = MapGenerator::default();
+        g.set_seed(21);
+        let m = g.create_map(6, 4);
+
+        // text without colour matches the console drawing once its escapes are removed
+        let plain = m.board_tiles_string(false);
+        assert!(plain.starts_with("Board: 6w x 4h"));
+        assert!(!plain.contains('\u{1b}'));
+        assert_eq!(plain.lines().count(), m.board_tiles_string(true).lines().count());
+        assert!(m.board_owners_string(false).contains("| A |"));
+
+        for (name, format, extension) in [("ascii", MapFormat::Ascii, "txt"), ("Owners", MapFormat::Owners, "txt"), ("png", MapFormat::Png, "png"), ("SVG", MapFormat::Svg, "svg")]{
+            assert_eq!(name.parse::<MapFormat>(), Ok(format));
+            assert_eq!(format.to_string().parse::<MapFormat>(), Ok(format));
+            assert_eq!(format.extension(), extension);
+        }
+        assert!(matches!("bmp".parse::<MapFormat>(), Err(MapGenError::Parse(_))));
+        assert_eq!(MapFormat::Ascii.to_text(&m, false), Some(plain.clone()));
+        assert_eq!(MapFormat::Png.to_text(&m, false), None);
+        #[cfg(feature = "serde")]
+        assert_eq!(MapFormat::Json.to_text(&m, false), Some(m.to_json()));
+
+        let path = std::env::temp_dir().join(format!("map_formats_{}.txt", std::process::id()));
+        MapFormat::Ascii.save(&m, &path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), plain);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(MapFormat::Svg.save(&m, "/nonexistent/dir/map.svg"), Err(MapGenError::Io(_))));
+    }
+
+    #[test]
+    fn campaign_battle_inputs(){
+        let mut campaign = CampaignMap::new(5, 5).unwrap();
+        for (r, c, t) in [(1, 1, "Town"), (1, 2, "Forest"), (2, 2, "Plains+Road"), (2, 3, "Hill+River"), (3, 2, "Mountain"), (0, 1, "Hill"), (3, 1, "Swamp"), (2, 1, "Plains")]{
+            campaign.set_tile(r, c, t.parse().unwrap()).unwrap();
+        }
+
+        // attacking from the upper right of an even row, the defender's left is upper left and right is right
+        let inputs = campaign.battle_inputs((1, 2), (2, 2)).unwrap();
+        assert_eq!(inputs.get_attacker().to_string(), "Forest");
+        assert_eq!(inputs.get_defender().to_string(), "Plains+Road");
+        assert_eq!(inputs.get_left_flank().to_string(), "Town");
+        assert_eq!(inputs.get_right_flank().to_string(), "Hill+River");
+        assert_eq!((inputs.get_cities_within_search_radius(), inputs.get_rivers_within_search_radius(),
+            inputs.get_mountains_within_search_radius(), inputs.get_hills_within_search_radius()), (1, 1, 1, 1));
+        assert_eq!(inputs.get_search_radius(), 1);
+        assert_eq!(inputs.get_attack_direction(), HexDirection::UpperRight);
+
+        // a wider search picks up the hill two hexes away
+        campaign.set_search_radius(2);
+        let inputs = campaign.battle_inputs((1, 2), (2, 2)).unwrap();
+        assert_eq!(inputs.get_hills_within_search_radius(), 2);
+        assert_eq!(inputs.get_search_radius(), 2);
+
+        // a search wider than the campaign counts every tile once
+        campaign.set_search_radius(u32::MAX);
+        let inputs = campaign.battle_inputs((1, 2), (2, 2)).unwrap();
+        assert_eq!((inputs.get_cities_within_search_radius(), inputs.get_rivers_within_search_radius(),
+            inputs.get_mountains_within_search_radius(), inputs.get_hills_within_search_radius()), (1, 1, 1, 2));
+        campaign.set_search_radius(2);
+
+        // odd row, attacking from the upper left
+        let inputs = campaign.battle_inputs((2, 2), (3, 2)).unwrap();
+        assert_eq!(inputs.get_left_flank().to_string(), "Swamp");
+        assert_eq!(inputs.get_right_flank().to_string(), "Hill+River");
+        assert_eq!(inputs.get_attack_direction(), HexDirection::UpperLeft);
+
+        // flanks off the board are default tiles
+        let inputs = campaign.battle_inputs((0, 1), (0, 0)).unwrap();
+        assert_eq!(inputs.get_left_flank(), &CampaignMapTile::default());
+
+        assert_eq!(campaign.battle_inputs((0, 0), (2, 2)), Err(MapGenError::NotAdjacent { attacker: (0, 0), defender: (2, 2) }));
+        assert!(matches!(campaign.battle_inputs((5, 0), (4, 0)), Err(MapGenError::OutOfBounds { .. })));
+        assert!(matches!(CampaignMap::new(0, 3), Err(MapGenError::BoardTooSmall { .. })));
+        assert!(matches!(CampaignMap::from_tiles(2, 2, vec![CampaignMapTile::default(); 3]), Err(MapGenError::TileCountMismatch { .. })));
+
+        // deserializing checks the board like from_tiles
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&campaign).unwrap();
+            assert_eq!(serde_json::from_str::<CampaignMap>(&json).unwrap(), campaign);
+            assert!(serde_json::from_str::<CampaignMap>(&json.replace("\"width\":5", "\"width\":9")).is_err());
+            assert!(serde_json::from_str::<CampaignMap>(&json.replace("\"height\":5", "\"height\":0")).is_err());
+        }
+    }
+
+    #[test]
+    fn campaign_files(){
+        let text = "# river valley\nsearch_radius = 2\n\nPlains  Forest+Road Hill\nTown+River Plains+ford Mountain  # pass\n";
+        let campaign = CampaignMap::from_text(text).unwrap();
+        assert_eq!((campaign.get_width(), campaign.get_height(), campaign.get_search_radius()), (3, 2, 2));
+        assert_eq!(campaign.get_tile(1, 1).unwrap().to_string(), "Plains+RiverFord");
+        assert_eq!(CampaignMap::from_text("Plains Hill").unwrap().get_search_radius(), 1);
+
+        // errors name the line they were found on
+        assert_eq!(CampaignMap::from_text("Plains Hill\n\nPlains Castle"),
+            Err(MapGenError::CampaignFile { line: 3, message: "Unknown campaign tile type: Castle".to_string() }));
+        assert_eq!(CampaignMap::from_text("Plains Hill\nPlains"),
+            Err(MapGenError::CampaignFile { line: 2, message: "Row has 1 tiles, expected 2".to_string() }));
+        assert!(matches!(CampaignMap::from_text("radius = 2\nPlains"), Err(MapGenError::CampaignFile { line: 1, .. })));
+        assert!(matches!(CampaignMap::from_text("# nothing here"), Err(MapGenError::BoardTooSmall { .. })));
+
+        let path = std::env::temp_dir().join(format!("campaign_files_{}.txt", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let loaded = campaign::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, campaign);
+        assert!(matches!(campaign::load(&path), Err(MapGenError::Io(_))));
+
+        // a battle straight from the file
+        let mut g = MapGenerator::new(loaded.battle_inputs((0, 1), (1, 1)).unwrap());
+        g.set_seed(5);
+        assert_eq!(g.create_map(8, 6).get_campaign_tiles().get_defender().to_string(), "Plains+RiverFord");
+
+        #[cfg(feature = "serde")]
+        {
+            let json = "{\n  \"search_radius\": 2,\n  \"rows\": [\n    [\"Plains\", \"Forest+Road\", \"Hill\"],\n    [\"Town+River\", \"Plains+ford\", \"Mountain\"]\n  ]\n}";
+            assert_eq!(CampaignMap::from_json(json).unwrap(), campaign);
+            assert!(matches!(CampaignMap::from_json(&json.replace("Mountain", "Castle")), Err(MapGenError::CampaignFile { line: 5, .. })));
+            // a short row is caught once the parser has moved past it
+            assert!(matches!(CampaignMap::from_json(&json.replace(", \"Mountain\"", "")), Err(MapGenError::CampaignFile { line: 6, .. })));
+        }
+        #[cfg(feature = "toml")]
+        {
+            let toml = "search_radius = 2\nrows = [\n  [\"Plains\", \"Forest+Road\", \"Hill\"],\n  [\"Town+River\", \"Plains+ford\", \"Mountain\"],\n]\n";
+            assert_eq!(CampaignMap::from_toml(toml).unwrap(), campaign);
+            assert!(matches!(CampaignMap::from_toml(&toml.replace("Hill", "Castle")), Err(MapGenError::CampaignFile { line: 3, .. })));
+        }
+    }
+
+    #[test]
+    fn attack_direction(){
+        let river = CampaignMapTile::new(CampaignMapTileType::Plains, Some(CampaignMapTileAddOn::River));
+        let road = CampaignMapTile::new(CampaignMapTileType::Forest, Some(CampaignMapTileAddOn::Road));
+        let plains = CampaignMapTile::new(CampaignMapTileType::Plains, None);
+        for direction in HexDirection::ALL{
+            let mut tiles = CampaignGenerationTiles::new(river.clone(), river.clone(), road.clone(), plains.clone(), 0, 0, 0, 0, 1);
+            tiles.set_attack_direction(direction);
+            let attack_edge = BoardEdge::from_direction(direction);
+            assert_eq!(BoardEdge::of_owner(&TileOwner::Attacker, direction), Some(attack_edge));
+            assert_eq!(BoardEdge::of_owner(&TileOwner::Defender, direction), Some(attack_edge.opposite()));
+            let mut g = MapGenerator::new(tiles);
+            g.set_seed(3);
+            let m = g.create_map(12, 9);
+
+            // the middle of each edge belongs to the owner behind it
+            for edge in BoardEdge::ALL{
+                let locations = m.edge_locations(edge);
+                let middle = locations[locations.len() / 2];
+                assert_eq!(m.get_tile(middle.0, middle.1).unwrap().get_owner(), &edge.owner(direction), "{} {:?}", direction, edge);
+            }
+
+            // the river crosses the attacker's and defender's edges and the road leaves by the left flank's
+            let exits = |network: &HexNetwork, edge: BoardEdge| m.edge_locations(edge).iter().any(|l| network.contains(*l) && network.is_exit(&m, *l));
+            assert!(exits(m.get_rivers(), attack_edge), "{}", direction);
+            assert!(exits(m.get_rivers(), attack_edge.opposite()), "{}", direction);
+            assert!(exits(m.get_roads(), BoardEdge::of_owner(&TileOwner::LeftFlank, direction).unwrap()), "{}", direction);
+        }
+
+        // the flanks are taken from the defender's side facing the attacker
+        assert_eq!(BoardEdge::Top.owner(HexDirection::Right), TileOwner::LeftFlank);
+        assert_eq!(BoardEdge::Bottom.owner(HexDirection::Left), TileOwner::LeftFlank);
+        assert_eq!(BoardEdge::Left.owner(HexDirection::LowerRight), TileOwner::RightFlank);
+        assert_eq!(CampaignGenerationTiles::default().get_attack_direction(), HexDirection::UpperRight);
+        assert_eq!("upper-left".parse(), Ok(HexDirection::UpperLeft));
+        for d in HexDirection::ALL{
+            assert_eq!(d.to_string().parse(), Ok(d));
+        }
+        assert!(matches!("up".parse::<HexDirection>(), Err(MapGenError::Parse(_))));
+    }
+
+    #[test]
+    fn deployment_layouts(){
+        let count = |owners: &[TileOwner], owner: TileOwner| owners.iter().filter(|o| **o == owner).count();
+        for layout in DeploymentLayout::BUILT_IN{
+            for direction in HexDirection::ALL{
+                let owners = layout.owners(15, 11, direction).unwrap();
+                assert_eq!(owners.len(), 15 * 11);
+            }
+            assert_eq!(layout.to_string().parse(), Ok(layout.clone()));
+        }
+
+        // the attacker pushes deeper on the defender's right than on their left
+        let flanking = DeploymentLayout::FlankingAssault.owners(15, 11, HexDirection::UpperRight).unwrap();
+        let depth = |c: usize| (0..11).filter(|r| flanking[r * 15 + c] == TileOwner::Attacker).count();
+        assert!(depth(11) > depth(3));
+        assert_eq!(count(&flanking, TileOwner::SplitAttDef), 0);
+
+        // the defender is surrounded in the middle
+        let encircled = DeploymentLayout::Encirclement.owners(15, 11, HexDirection::UpperRight).unwrap();
+        assert_eq!(encircled[5 * 15 + 7], TileOwner::Defender);
+        assert_eq!(encircled[7], TileOwner::Attacker);
+        assert_eq!(encircled[10 * 15 + 7], TileOwner::Attacker);
+
+        // a meeting engagement contests the middle third, and flanks are narrower
+        let meeting = DeploymentLayout::MeetingEngagement.owners(15, 11, HexDirection::UpperRight).unwrap();
+        assert_eq!(count(&meeting, TileOwner::SplitAttDef), 3 * 11);
+        assert!(count(&meeting, TileOwner::LeftFlank) < count(&encircled, TileOwner::LeftFlank));
+
+        // a river crossing runs a river between the flanks, even with no river on the campaign,
+        // and the attacker cannot reach the defender without crossing it
+        for direction in HexDirection::ALL{
+            let mut tiles = CampaignGenerationTiles::default();
+            tiles.set_attack_direction(direction);
+            let mut g = MapGenerator::new(tiles);
+            g.set_layout(DeploymentLayout::RiverCrossing);
+            for seed in 0..20{
+                g.set_seed(seed);
+                let m = g.create_map(15, 11);
+                let rivers = m.get_rivers();
+                for owner in [TileOwner::LeftFlank, TileOwner::RightFlank]{
+                    let edge = BoardEdge::of_owner(&owner, direction).unwrap();
+                    assert!(m.edge_locations(edge).iter().any(|l| rivers.contains(*l) && rivers.is_exit(&m, *l)), "{:?} {} seed {}", edge, direction, seed);
+                }
+                let owner = |l: (usize, usize)| m.get_tile(l.0, l.1).unwrap().get_owner().clone();
+                let mut reached: Vec<(usize, usize)> = (0..11).flat_map(|r| (0..15).map(move |c| (r, c)))
+                    .filter(|l| owner(*l) == TileOwner::Attacker && !rivers.contains(*l))
+                    .collect();
+                let mut queue = reached.clone();
+                while let Some(l) = queue.pop(){
+                    for (_, n) in m.neighbor_locations(l.0, l.1){
+                        if !rivers.contains(n) && !reached.contains(&n){
+                            assert_ne!(owner(n), TileOwner::Defender, "{} seed {}", direction, seed);
+                            reached.push(n);
+                            queue.push(n);
+                        }
+                    }
+                }
+            }
+        }
+
+        // the river split is checked once the rivers are down: with no river the defender's bank is gone
+        let m = MapGenerator::default().create_map(15, 11);
+        let owners: Vec<TileOwner> = (0..11).flat_map(|r| (0..15).map(move |c| (r, c)))
+            .map(|l| m.get_tile(l.0, l.1).unwrap().get_owner().clone())
+            .collect();
+        assert_eq!(DeploymentLayout::Classic.finalize(&m), Ok(owners));
+        assert_eq!(DeploymentLayout::RiverCrossing.finalize(&m), Err(MapGenError::EmptyDeployment { owner: TileOwner::Defender }));
+
+        // edges are held by whoever the layout puts along them, the encircled defender holds none
+        for owner in [TileOwner::Attacker, TileOwner::Defender, TileOwner::LeftFlank, TileOwner::RightFlank]{
+            assert_eq!(m.edge_of_owner(&owner), BoardEdge::of_owner(&owner, HexDirection::UpperRight));
+        }
+        let mut g = MapGenerator::default();
+        g.set_layout(DeploymentLayout::Encirclement);
+        let encircled = g.create_map(15, 11);
+        assert_eq!(encircled.edge_of_owner(&TileOwner::Defender), None);
+        assert_eq!(encircled.edge_of_owner(&TileOwner::LeftFlank), Some(BoardEdge::Left));
+
+        // custom masks are used as drawn
+        let mask = DeploymentLayout::from_mask("L A A R\nL S S R\nL D D R\n").unwrap();
+        let mut g = MapGenerator::default();
+        g.set_layout(mask.clone());
+        let m = g.create_map(4, 3);
+        assert_eq!(m.get_tile(1, 2).unwrap().get_owner(), &TileOwner::SplitAttDef);
+        assert_eq!(m.get_tile(2, 1).unwrap().get_owner(), &TileOwner::Defender);
+        assert_eq!(g.try_create_map(5, 3).err(), Some(MapGenError::MaskSizeMismatch { board: (5, 3), mask: (4, 3) }));
+        let short = DeploymentLayout::Custom { width: 4, height: 3, owners: vec![TileOwner::Attacker; 5] };
+        assert_eq!(short.owners(4, 3, HexDirection::UpperRight), Err(MapGenError::TileCountMismatch { expected: 12, found: 5 }));
+        assert!(matches!(DeploymentLayout::from_mask("L A\nL X"), Err(MapGenError::Parse(_))));
+        assert!(matches!(DeploymentLayout::from_mask("L A A\nL D"), Err(MapGenError::Parse(_))));
+
+        // every owner needs somewhere to deploy
+        g.set_layout(DeploymentLayout::from_mask("L A A A\nL D D D").unwrap());
+        assert_eq!(g.try_create_map(4, 2).err(), Some(MapGenError::EmptyDeployment { owner: TileOwner::RightFlank }));
+        g.set_layout(DeploymentLayout::Encirclement);
+        assert_eq!(g.try_create_map(4, 2).err(), Some(MapGenError::EmptyDeployment { owner: TileOwner::Attacker }));
+        assert!(matches!("ambush".parse::<DeploymentLayout>(), Err(MapGenError::Parse(_))));
     }
-}
\ No newline at end of file
+}
@@ -1,34 +1,362 @@
-use map_generator::battle_map::{MapGenerator};
-use map_generator::map_tiles::{CampaignGenerationTiles, CampaignMapTile};
-
-fn main() {
-
-    for i in 4..=5{
-        for j in 4..=5{
-            let m = MapGenerator::new(CampaignGenerationTiles::new(
-                CampaignMapTile::default(), 
-                CampaignMapTile::default(), 
-                CampaignMapTile::default(), 
-                CampaignMapTile::default(), 
-                1, 1, 1, 1, 1,))
-                .create_map(i,j);
-            m.print_board_tiles();
+use clap::{Args, Parser, Subcommand};
+use map_generator::battle_map::{HexDirection, Map, MapGenerator};
+use map_generator::campaign;
+use map_generator::deployment::DeploymentLayout;
+use map_generator::errors::MapGenError;
+use map_generator::map_tiles::{BattleMapTileType, CampaignGenerationTiles, CampaignMapTile};
+use map_generator::output::MapFormat;
+use map_generator::render::{tile_name, TILE_TYPES};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Generate hex battle maps from the campaign tiles around a battle
+#[derive(Parser, Debug)]
+#[command(name = "map_generator", version)]
+struct Cli{
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command{
+    /// Generate a battle map
+    Generate{
+        #[command(flatten)]
+        map: MapArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Draw a map saved as JSON in another format
+    #[cfg(feature = "serde")]
+    Render{
+        /// Map saved with `--format json`
+        input: PathBuf,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Summarise a generated map, or one saved as JSON
+    Inspect{
+        /// Map saved with `--format json`, a new map is generated from the flags when left out
+        #[cfg(feature = "serde")]
+        // clap leaves the MapArgs group empty because it flattens BoardArgs, so its own flags are listed
+        #[arg(conflicts_with_all = ["BoardArgs", "attacker", "defender", "left_flank", "right_flank",
+            "cities", "rivers", "mountains", "hills", "search_radius", "attack_direction"])]
+        input: Option<PathBuf>,
+        #[command(flatten)]
+        map: MapArgs,
+    },
+    /// Generate the battle between two neighboring hexes of a campaign map file
+    Campaign{
+        /// Campaign map, .json and .toml files are read in those formats and anything else as text
+        file: PathBuf,
+        /// Attacker's campaign hex as row,column
+        #[arg(long, value_parser = parse_hex)]
+        attacker_hex: (usize, usize),
+        /// Defender's campaign hex as row,column
+        #[arg(long, value_parser = parse_hex)]
+        defender_hex: (usize, usize),
+        /// Campaign hexes searched around the battle, overrides the file
+        #[arg(long)]
+        search_radius: Option<u32>,
+        #[command(flatten)]
+        board: BoardArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Generate a series of maps with consecutive seeds into a directory
+    Batch{
+        /// Number of maps to generate
+        #[arg(long, default_value_t = 10)]
+        count: u64,
+        #[command(flatten)]
+        map: MapArgs,
+        /// Output format
+        #[arg(short, long, default_value = "png")]
+        format: MapFormat,
+        /// Directory to write the maps into, named map_<seed>.<ext>
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+}
+
+/// Board size and generation settings
+#[derive(Args, Debug)]
+struct BoardArgs{
+    /// Board width in hexes
+    #[arg(long, default_value_t = 15)]
+    width: usize,
+    /// Board height in hexes
+    #[arg(long, default_value_t = 11)]
+    height: usize,
+    /// Seed for a reproducible map, random when left out
+    #[arg(long)]
+    seed: Option<u64>,
+    /// How closely each region follows its campaign tile, 0 to 1
+    #[arg(long)]
+    similarity: Option<f64>,
+    /// Deployment layout: classic, flanking-assault, encirclement, meeting-engagement or river-crossing
+    #[arg(long, default_value = "classic")]
+    layout: DeploymentLayout,
+    /// File with a custom deployment layout, one row per line of A, D, L, R or S, used instead of --layout
+    #[arg(long, value_parser = load_mask)]
+    layout_mask: Option<DeploymentLayout>,
+    /// Fill the ground around the rivers, roads and towns by wave function collapse instead of by sector
+    #[arg(long)]
+    wfc_terrain: bool,
+}
+
+/// Board settings and campaign inputs, tiles are written as Type or Type+AddOn, e.g. Forest+Road
+#[derive(Args, Debug)]
+struct MapArgs{
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Campaign tile of the attacker, along the edge the attack comes from
+    #[arg(long, default_value = "Plains")]
+    attacker: CampaignMapTile,
+    /// Campaign tile of the defender, along the opposite edge
+    #[arg(long, default_value = "Plains")]
+    defender: CampaignMapTile,
+    /// Campaign tile on the left flank
+    #[arg(long, default_value = "Plains")]
+    left_flank: CampaignMapTile,
+    /// Campaign tile on the right flank
+    #[arg(long, default_value = "Plains")]
+    right_flank: CampaignMapTile,
+    /// Cities within the search radius
+    #[arg(long, default_value_t = 0)]
+    cities: u32,
+    /// Rivers within the search radius
+    #[arg(long, default_value_t = 0)]
+    rivers: u32,
+    /// Mountains within the search radius
+    #[arg(long, default_value_t = 0)]
+    mountains: u32,
+    /// Hills within the search radius
+    #[arg(long, default_value_t = 0)]
+    hills: u32,
+    /// Campaign hexes searched around the battle
+    #[arg(long, default_value_t = 1)]
+    search_radius: u32,
+    /// Direction the attack comes from: Right, LowerRight, LowerLeft, Left, UpperLeft or UpperRight
+    #[arg(long, default_value = "UpperRight")]
+    attack_direction: HexDirection,
+}
+
+#[derive(Args, Debug)]
+struct OutputArgs{
+    #[cfg_attr(feature = "serde", doc = "Output format: ascii, owners, json, png or svg")]
+    #[cfg_attr(not(feature = "serde"), doc = "Output format: ascii, owners, png or svg")]
+    #[arg(short, long, default_value = "ascii")]
+    format: MapFormat,
+    /// File to write, text formats print to the console when left out
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl BoardArgs{
+    fn generator(&self, tiles: CampaignGenerationTiles) -> MapGenerator{
+        let mut g = MapGenerator::new(tiles);
+        g.set_layout(self.layout_mask.clone().unwrap_or_else(|| self.layout.clone()));
+        g.set_wfc_terrain(self.wfc_terrain);
+        if let Some(s) = self.similarity{
+            g.set_campaign_similarity(s);
+        }
+        if let Some(s) = self.seed{
+            g.set_seed(s);
+        }
+        g
+    }
+
+    fn generate(&self, tiles: CampaignGenerationTiles) -> Result<Map, MapGenError>{
+        self.generator(tiles).try_create_map(self.width, self.height)
+    }
+}
+
+impl MapArgs{
+    fn tiles(&self) -> CampaignGenerationTiles{
+        let mut tiles = CampaignGenerationTiles::new(
+            self.attacker.clone(), self.defender.clone(), self.left_flank.clone(), self.right_flank.clone(),
+            self.cities, self.rivers, self.mountains, self.hills, self.search_radius);
+        tiles.set_attack_direction(self.attack_direction);
+        tiles
+    }
+
+    fn generate(&self) -> Result<Map, MapGenError>{
+        self.board.generate(self.tiles())
+    }
+}
+
+/// Read a custom deployment layout from a mask file
+fn load_mask(path: &str) -> Result<DeploymentLayout, String>{
+    fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path, e))
+        .and_then(|text| DeploymentLayout::from_mask(&text).map_err(|e| e.to_string()))
+}
+
+/// Parse a campaign hex written as row,column
+fn parse_hex(s: &str) -> Result<(usize, usize), String>{
+    s.split_once(',')
+        .and_then(|(r, c)| Some((r.trim().parse().ok()?, c.trim().parse().ok()?)))
+        .ok_or_else(|| format!("Expected row,column, found {}", s))
+}
+
+impl OutputArgs{
+    /// Write the map to the output file, or print it when there is none,
+    /// image formats default to map_<seed>.<ext>
+    fn write(&self, map: &Map) -> Result<(), MapGenError>{
+        match (&self.output, self.format.to_text(map, true)){
+            (None, Some(text)) => print!("{}", text),
+            (Some(path), _) => self.format.save(map, path)?,
+            (None, None) => {
+                let path = PathBuf::from(format!("map_{}.{}", map.get_seed(), self.format.extension()));
+                self.format.save(map, &path)?;
+                println!("Saved {}", path.display());
+            },
         }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+fn load(path: &PathBuf) -> Result<Map, MapGenError>{
+    Map::from_json(&fs::read_to_string(path)?)
+}
+
+/// Board size, inputs and what was placed on the map
+fn inspect(map: &Map){
+    let tiles = map.get_campaign_tiles();
+    println!("Board: {}w x {}h, seed {}", map.get_board_width(), map.get_board_height(), map.get_seed());
+    println!("Attacker: {}, defender: {}, left flank: {}, right flank: {}, attacking from {}",
+        tiles.get_attacker(), tiles.get_defender(), tiles.get_left_flank(), tiles.get_right_flank(), tiles.get_attack_direction());
+    println!("Within radius {}: {} cities, {} rivers, {} mountains, {} hills",
+        tiles.get_search_radius(), tiles.get_cities_within_search_radius(), tiles.get_rivers_within_search_radius(),
+        tiles.get_mountains_within_search_radius(), tiles.get_hills_within_search_radius());
+    for t in TILE_TYPES.iter().filter(|t| **t != BattleMapTileType::Default){
+        println!("{:>9}: {}", tile_name(t), map.locations_of(t).len());
+    }
+    let all: Vec<_> = (0..map.get_board_height())
+        .flat_map(|r| (0..map.get_board_width()).map(move |c| (r, c)))
+        .filter_map(|(r, c)| map.get_tile(r, c))
+        .collect();
+    println!("River hexes: {}, road hexes: {}", map.get_rivers().len(), map.get_roads().len());
+    println!("Crossings: {}, fortifications: {}",
+        all.iter().filter(|t| t.get_crossing().is_some()).count(),
+        all.iter().filter(|t| t.get_add_on().is_some()).count());
+    let heights = all.iter().map(|t| t.get_elevation());
+    println!("Elevation: {} to {}", heights.clone().min().unwrap_or(0), heights.max().unwrap_or(0));
+}
+
+fn run(cli: Cli) -> Result<(), MapGenError>{
+    match cli.command{
+        Command::Generate { map, output } => output.write(&map.generate()?),
+        #[cfg(feature = "serde")]
+        Command::Render { input, output } => output.write(&load(&input)?),
+        #[cfg(feature = "serde")]
+        Command::Inspect { input: Some(input), .. } => {
+            inspect(&load(&input)?);
+            Ok(())
+        },
+        Command::Inspect { map, .. } => {
+            inspect(&map.generate()?);
+            Ok(())
+        },
+        Command::Campaign { file, attacker_hex, defender_hex, search_radius, board, output } => {
+            let mut campaign = campaign::load(&file)?;
+            if let Some(r) = search_radius{
+                campaign.set_search_radius(r);
+            }
+            output.write(&board.generate(campaign.battle_inputs(attacker_hex, defender_hex)?)?)
+        },
+        Command::Batch { count, map, format, output } => {
+            fs::create_dir_all(&output)?;
+            let first = map.board.seed.unwrap_or_else(rand::random);
+            let mut g = map.board.generator(map.tiles());
+            for seed in (0..count).map(|i| first.wrapping_add(i)){
+                g.set_seed(seed);
+                let path = output.join(format!("map_{}.{}", seed, format.extension()));
+                format.save(&g.try_create_map(map.board.width, map.board.height)?, &path)?;
+                println!("Saved {}", path.display());
+            }
+            Ok(())
+        },
     }
+}
 
-    // MapGenerator::default().create_default_map().print_board_tiles();
+fn main() -> ExitCode{
+    match run(Cli::parse()){
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
 
-    // let m = Map::create_map(4, 6, vec![]);
-    // m.print_board();
+#[cfg(test)]
+mod tests{
+    use super::*;
 
+    #[test]
+    fn cli_arguments(){
+        let cli = Cli::try_parse_from(["map_generator", "generate", "--width", "12", "--height", "8", "--seed", "7",
+            "--defender", "Town+River", "--cities", "2", "--format", "svg", "-o", "map.svg"]).unwrap();
+        let Command::Generate { map, output } = cli.command else { panic!("expected generate") };
+        assert_eq!((map.board.width, map.board.height, map.board.seed), (12, 8, Some(7)));
+        assert_eq!(map.defender.to_string(), "Town+River");
+        assert_eq!(output.format, MapFormat::Svg);
+        assert_eq!(output.output, Some(PathBuf::from("map.svg")));
+        let m = map.generate().unwrap();
+        assert_eq!(m.get_seed(), 7);
+        assert_eq!(m.get_campaign_tiles().get_cities_within_search_radius(), 2);
 
-    // for i in 4..=5{
-    //     for j in 2..=5{
-    //         let m = MapGenerator::default().create_map(i,j);
-    //         m.print_board_owners();
-    //     }
-    // }
+        assert!(Cli::try_parse_from(["map_generator", "generate", "--attacker", "Castle"]).is_err());
+        assert!(Cli::try_parse_from(["map_generator", "generate", "--format", "bmp"]).is_err());
+        let cli = Cli::try_parse_from(["map_generator", "generate", "--attack-direction", "lower-left"]).unwrap();
+        let Command::Generate { map, .. } = cli.command else { panic!("expected generate") };
+        assert_eq!(map.tiles().get_attack_direction(), HexDirection::LowerLeft);
+        assert_eq!(map.board.layout, DeploymentLayout::Classic);
+        let cli = Cli::try_parse_from(["map_generator", "generate", "--layout", "river-crossing"]).unwrap();
+        let Command::Generate { map, .. } = cli.command else { panic!("expected generate") };
+        assert_eq!(map.board.generator(map.tiles()).get_layout(), &DeploymentLayout::RiverCrossing);
+        assert!(Cli::try_parse_from(["map_generator", "generate", "--layout", "ambush"]).is_err());
+        assert!(Cli::try_parse_from(["map_generator", "generate", "--layout-mask", "/nonexistent/mask.txt"]).is_err());
+        assert!(Cli::try_parse_from(["map_generator", "batch", "--count", "3", "-o", "maps"]).is_ok());
+        assert!(Cli::try_parse_from(["map_generator", "inspect", "--seed", "3"]).is_ok());
+        #[cfg(feature = "serde")]
+        {
+            assert!(Cli::try_parse_from(["map_generator", "inspect", "map.json"]).is_ok());
+            assert!(Cli::try_parse_from(["map_generator", "inspect", "map.json", "--seed", "3"]).is_err());
+            assert!(Cli::try_parse_from(["map_generator", "inspect", "map.json", "--defender", "Town"]).is_err());
+        }
+        #[cfg(not(feature = "serde"))]
+        assert!(Cli::try_parse_from(["map_generator", "generate", "--format", "json"]).is_err());
+        let cli = Cli::try_parse_from(["map_generator", "generate", "--wfc-terrain"]).unwrap();
+        let Command::Generate { map, .. } = cli.command else { panic!("expected generate") };
+        assert!(map.board.generator(map.tiles()).get_wfc_terrain());
 
-    // MapGenerator::default().create_default_map().print_board_owners();
+        // batch writes one file per seed
+        let dir = std::env::temp_dir().join(format!("map_generator_batch_{}", std::process::id()));
+        let cli = Cli::try_parse_from(["map_generator", "batch", "--count", "2", "--seed", "40", "--width", "6", "--height", "4",
+            "-f", "ascii", "-o", dir.to_str().unwrap()]).unwrap();
+        run(cli).unwrap();
+        assert!(dir.join("map_40.txt").exists());
+        assert!(dir.join("map_41.txt").exists());
+        assert!(!fs::read_to_string(dir.join("map_40.txt")).unwrap().contains('\u{1b}'));
+        fs::remove_dir_all(dir).unwrap();
 
+        // a battle from a campaign file and two hexes
+        let file = std::env::temp_dir().join(format!("map_generator_campaign_{}.txt", std::process::id()));
+        fs::write(&file, "Forest Hill+Road\nTown+River Plains\n").unwrap();
+        let map_file = file.with_extension("out");
+        let cli = Cli::try_parse_from(["map_generator", "campaign", file.to_str().unwrap(), "--attacker-hex", "0,1", "--defender-hex", "1,1",
+            "--width", "6", "--height", "4", "-o", map_file.to_str().unwrap()]).unwrap();
+        run(cli).unwrap();
+        assert!(fs::read_to_string(&map_file).unwrap().contains("Board: 6w x 4h"));
+        let cli = Cli::try_parse_from(["map_generator", "campaign", file.to_str().unwrap(), "--attacker-hex", "0,0", "--defender-hex", "1,1"]).unwrap();
+        assert!(matches!(run(cli), Err(MapGenError::NotAdjacent { .. })));
+        assert!(Cli::try_parse_from(["map_generator", "campaign", "c.txt", "--attacker-hex", "0", "--defender-hex", "1,1"]).is_err());
+        fs::remove_file(file).unwrap();
+        fs::remove_file(map_file).unwrap();
+    }
 }